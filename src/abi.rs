@@ -0,0 +1,148 @@
+use crate::{archive, util, PkgBuilderError, PkgFileBuild};
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
+
+// Compares the shared libraries in the out directory against the ones
+// of a previous build with abidiff (libabigail).
+// build.abi_check is the path of the previous tarball relative to the output path,
+// a <name>-dev.tar.gz next to it is extracted as well for the headers.
+// Incompatible changes to symbols not in abi_check_whitelist fail the build.
+pub fn check_abi(
+    build: &PkgFileBuild,
+    output_path: &str,
+    build_dir: &str,
+    out_dir: &str,
+) -> Result<(), PkgBuilderError> {
+    let Some(ref previous) = build.abi_check else {
+        return Ok(());
+    };
+
+    if !util::command_exists("abidiff") {
+        return Err(PkgBuilderError::ToolMissing {
+            tool: "abidiff".to_string(),
+        });
+    }
+
+    let old_dir = format!("{}_abi", build_dir);
+    fs::create_dir_all(&old_dir).expect("Unable to create directory");
+
+    let tarball = format!("{}/{}", output_path, previous);
+    let mut tarballs = vec![tarball.clone()];
+    if let Some(stem) = tarball.strip_suffix(".tar.gz") {
+        let dev_tarball = format!("{}-dev.tar.gz", stem);
+        if Path::new(&dev_tarball).is_file() {
+            tarballs.push(dev_tarball);
+        }
+    }
+    for tarball in &tarballs {
+        println!("Extracting {} for the ABI check", tarball);
+        if let Err(err) = archive::extract_tarball(tarball, &old_dir, &[]) {
+            fs::remove_dir_all(&old_dir).expect("Unable to remove directory");
+            return Err(err);
+        }
+    }
+
+    let old_libs = shared_libraries(&old_dir);
+    let new_libs = shared_libraries(out_dir);
+    let whitelist = build.abi_check_whitelist.as_deref().unwrap_or_default();
+
+    let mut changed_symbols = Vec::new();
+    for (name, new_lib) in &new_libs {
+        let Some(old_lib) = old_libs.get(name) else {
+            println!("No previous version of {}, skipping the ABI check", name);
+            continue;
+        };
+
+        println!("Checking the ABI of {}", name);
+        let output = Command::new("abidiff")
+            .arg("--headers-dir1")
+            .arg(format!("{}/usr/include", old_dir))
+            .arg("--headers-dir2")
+            .arg(format!("{}/usr/include", out_dir))
+            .arg(old_lib)
+            .arg(new_lib)
+            .output()
+            .expect("Failed to execute command");
+
+        // the exit code is a bit field: 1 error, 2 usage error,
+        // 4 ABI change, 8 incompatible ABI change
+        let status = output.status.code().unwrap_or(1);
+        if status & 3 != 0 {
+            fs::remove_dir_all(&old_dir).expect("Unable to remove directory");
+            return Err(PkgBuilderError::ABICheckFailed {
+                library: name.clone(),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        if status & 8 == 0 {
+            if status & 4 != 0 {
+                println!("{} has compatible ABI changes", name);
+            }
+            continue;
+        }
+
+        for symbol in changed(&String::from_utf8_lossy(&output.stdout)) {
+            if whitelist.contains(&symbol) {
+                eprintln!("Warning: ignoring the ABI change of {} in {}", symbol, name);
+            } else {
+                changed_symbols.push(format!("{} ({})", symbol, name));
+            }
+        }
+    }
+
+    fs::remove_dir_all(&old_dir).expect("Unable to remove directory");
+
+    if changed_symbols.is_empty() {
+        println!("ABI check passed");
+        Ok(())
+    } else {
+        Err(PkgBuilderError::ABIIncompatible { changed_symbols })
+    }
+}
+
+// shared libraries below a directory by their name without the version, e.g. libfoo.so
+fn shared_libraries(dir: &str) -> BTreeMap<String, String> {
+    let mut libs = BTreeMap::new();
+    for file in util::walk_files(Path::new(dir)) {
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        let Some((stem, _)) = file_name.split_once(".so") else {
+            continue;
+        };
+        if file.is_symlink() || !util::is_elf(&file) {
+            continue;
+        }
+        libs.insert(format!("{}.so", stem), file.to_string_lossy().to_string());
+    }
+    libs
+}
+
+// symbols listed as removed ([D]) or changed ([C]) in an abidiff report
+//   [D] 'function int foo(int)'    {foo}
+//   [C] 'function void bar(baz*)' at bar.c:3:1 has some indirect sub-type changes:
+fn changed(report: &str) -> Vec<String> {
+    let mut symbols = Vec::new();
+    for line in report.lines() {
+        let line = line.trim_start();
+        if !(line.starts_with("[D]") || line.starts_with("[C]")) {
+            continue;
+        }
+
+        // the ELF symbol in braces, otherwise the name from the declaration
+        let symbol = match line.rsplit_once('{') {
+            Some((_, rest)) if rest.ends_with('}') => rest.trim_end_matches('}').to_string(),
+            _ => {
+                let declaration = line.split('\'').nth(1).unwrap_or_default();
+                let declaration = declaration.split('(').next().unwrap_or_default();
+                declaration
+                    .split_whitespace()
+                    .last()
+                    .unwrap_or_default()
+                    .to_string()
+            }
+        };
+
+        if !symbol.is_empty() && !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+    symbols
+}
@@ -0,0 +1,159 @@
+use crate::PkgBuilderError;
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// Tarball handling shared by source fetching and the extract subcommand.
+// Compression (gzip, xz, bzip2, zstd) is detected by tar itself.
+
+// lists the entries of a tarball
+pub fn list_tarball(archive: &str) -> Result<Vec<String>, PkgBuilderError> {
+    let output = Command::new("tar")
+        .arg("-tf")
+        .arg(archive)
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        return Err(PkgBuilderError::ExtractionFailed {
+            archive: archive.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+// extracts a tarball into destination and returns the extracted entries.
+// entries with absolute paths or `..` components are rejected
+// before anything is written
+// entries listed in exclude (e.g. ./package.toml) are skipped
+pub fn extract_tarball(
+    archive: &str,
+    destination: &str,
+    exclude: &[String],
+) -> Result<Vec<String>, PkgBuilderError> {
+    let entries = list_tarball(archive)?;
+
+    if let Some(entry) = entries.iter().find(|entry| !is_safe_path(entry)) {
+        return Err(PkgBuilderError::UnsafeArchivePath {
+            archive: archive.to_string(),
+            path: entry.to_string(),
+        });
+    }
+
+    let output = Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("-C")
+        .arg(destination)
+        .arg("--anchored")
+        .args(exclude.iter().map(|entry| format!("--exclude={}", entry)))
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        return Err(PkgBuilderError::ExtractionFailed {
+            archive: archive.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+
+    Ok(entries)
+}
+
+// extracts an encrypted zip archive with python's zipfile module.
+// the password is written to its stdin, unzip -P would show it in the
+// process list. entries with absolute paths or `..` components are rejected
+const UNZIP_SCRIPT: &str = r#"
+import os, sys, zipfile
+archive, destination = sys.argv[1], sys.argv[2]
+password = sys.stdin.buffer.read()
+with zipfile.ZipFile(archive) as zip:
+    for name in zip.namelist():
+        if name.startswith("/") or ".." in name.split("/"):
+            sys.exit("unsafe path: " + name)
+    try:
+        zip.extractall(destination, pwd=password)
+    except (RuntimeError, zipfile.BadZipFile) as err:
+        sys.exit(str(err))
+"#;
+
+pub fn extract_encrypted_zip(
+    archive: &str,
+    destination: &str,
+    password: &str,
+) -> Result<(), PkgBuilderError> {
+    let mut child = Command::new("python3")
+        .arg("-c")
+        .arg(UNZIP_SCRIPT)
+        .arg(archive)
+        .arg(destination)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+
+    child
+        .stdin
+        .take()
+        .expect("Failed to open stdin")
+        .write_all(password.as_bytes())
+        .expect("Failed to write to stdin");
+
+    let output = child.wait_with_output().expect("Failed to wait on child");
+    if !output.status.success() {
+        return Err(PkgBuilderError::ExtractionFailed {
+            archive: archive.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+// reads a single entry, returns None if it is not in the tarball
+pub fn read_entry(archive: &str, entry: &str) -> Option<Vec<u8>> {
+    let output = Command::new("tar")
+        .arg("-xOf")
+        .arg(archive)
+        .arg(entry)
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(output.stdout)
+}
+
+// runs a hook script like pre_install.sh from the root of a package tarball,
+// nothing happens when the package has no such hook
+pub fn run_hook(archive: &str, hook: &str, working_dir: &str) {
+    let Some(script) = read_entry(archive, &format!("./{}", hook)) else {
+        return;
+    };
+
+    println!("Running {}", hook);
+
+    let status = Command::new("bash")
+        .arg("-c")
+        .arg(String::from_utf8_lossy(&script).to_string())
+        .current_dir(working_dir)
+        .status()
+        .expect("Failed to execute command");
+
+    if !status.success() {
+        panic!("{} failed", hook);
+    }
+}
+
+fn is_safe_path(path: &str) -> bool {
+    !path.starts_with('/') && !path.split('/').any(|component| component == "..")
+}
@@ -0,0 +1,276 @@
+use crate::{util, PkgBuilderError, PkgFile};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+    process::Command,
+};
+
+// Audits the locked dependencies of every source directory with
+// cargo audit (Cargo.lock), npm audit (package-lock.json) or pip-audit (Pipfile.lock).
+// Only high and critical advisories fail the build, pip-audit reports
+// no severity so all of its findings count.
+// Advisories in ignore_ids are accepted.
+pub fn audit_sources(
+    pkgfile: &PkgFile,
+    build_dir: &str,
+    ignore_ids: &[String],
+) -> Result<(), PkgBuilderError> {
+    let mut dirs = BTreeSet::new();
+    dirs.insert(build_dir.to_string());
+    for source in pkgfile.source.iter().flatten() {
+        dirs.insert(source.destination_dir(build_dir));
+    }
+
+    let mut vulnerabilities = Vec::new();
+    for dir in dirs {
+        let dir = Path::new(&dir);
+        if dir.join("Cargo.lock").is_file() {
+            vulnerabilities.extend(cargo_audit(dir));
+        }
+        if dir.join("package-lock.json").is_file() {
+            vulnerabilities.extend(npm_audit(dir));
+        }
+        if dir.join("Pipfile.lock").is_file() {
+            vulnerabilities.extend(pip_audit(dir));
+        }
+    }
+
+    // (advisory id, description)
+    let vulnerabilities = vulnerabilities
+        .into_iter()
+        .filter(|(id, _)| {
+            let ignored = ignore_ids.contains(id);
+            if ignored {
+                eprintln!("Warning: ignoring advisory {}", id);
+            }
+            !ignored
+        })
+        .map(|(_, description)| description)
+        .collect::<Vec<_>>();
+
+    if vulnerabilities.is_empty() {
+        println!("Dependency audit passed");
+        Ok(())
+    } else {
+        Err(PkgBuilderError::SecurityAuditFailed { vulnerabilities })
+    }
+}
+
+fn cargo_audit(dir: &Path) -> Vec<(String, String)> {
+    let report = run_json(
+        Command::new("cargo").arg("audit").arg("--json"),
+        "cargo-audit",
+        dir,
+    );
+
+    report["vulnerabilities"]["list"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|vulnerability| {
+            // advisories without a CVSS v3 vector can't be rated and are kept
+            vulnerability["advisory"]["cvss"]
+                .as_str()
+                .and_then(cvss_base_score)
+                .is_none_or(|score| score >= 7.0)
+        })
+        .map(|vulnerability| {
+            let id = vulnerability["advisory"]["id"].as_str().unwrap_or_default();
+            let package = vulnerability["package"]["name"]
+                .as_str()
+                .unwrap_or_default();
+            (id.to_string(), format!("{} in {}", id, package))
+        })
+        .collect()
+}
+
+// base score of a CVSS v3 vector like CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H,
+// 7.0 and above is high, 9.0 and above critical
+fn cvss_base_score(vector: &str) -> Option<f64> {
+    let mut metrics = vector.split('/');
+    if !metrics.next()?.starts_with("CVSS:3") {
+        return None;
+    }
+    let metrics = metrics
+        .filter_map(|metric| metric.split_once(':'))
+        .collect::<BTreeMap<_, _>>();
+
+    let changed = match *metrics.get("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector: f64 = match *metrics.get("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let attack_complexity = match *metrics.get("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let privileges_required = match (*metrics.get("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let user_interaction = match *metrics.get("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    let impact = |metric: &str| match *metrics.get(metric)? {
+        "H" => Some(0.56),
+        "L" => Some(0.22),
+        "N" => Some(0.0),
+        _ => None,
+    };
+    let impact_subscore: f64 =
+        1.0 - (1.0 - impact("C")?) * (1.0 - impact("I")?) * (1.0 - impact("A")?);
+
+    let impact = if changed {
+        7.52 * (impact_subscore - 0.029) - 3.25 * (impact_subscore - 0.02).powi(15)
+    } else {
+        6.42 * impact_subscore
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability =
+        8.22 * attack_vector * attack_complexity * privileges_required * user_interaction;
+    let score = if changed {
+        1.08 * (impact + exploitability)
+    } else {
+        impact + exploitability
+    };
+
+    // rounded up to one decimal as in the specification
+    let score = (score.min(10.0) * 100_000.0).round() as u64;
+    if score.is_multiple_of(10_000) {
+        Some(score as f64 / 100_000.0)
+    } else {
+        Some((score / 10_000 + 1) as f64 / 10.0)
+    }
+}
+
+fn npm_audit(dir: &Path) -> Vec<(String, String)> {
+    let report = run_json(
+        Command::new("npm")
+            .arg("audit")
+            .arg("--audit-level=high")
+            .arg("--json"),
+        "npm",
+        dir,
+    );
+
+    let mut vulnerabilities = Vec::new();
+    for (package, vulnerability) in report["vulnerabilities"].as_object().into_iter().flatten() {
+        if !matches!(
+            vulnerability["severity"].as_str(),
+            Some("high" | "critical")
+        ) {
+            continue;
+        }
+
+        // advisories are objects in via, strings refer to other vulnerable packages
+        for advisory in vulnerability["via"].as_array().into_iter().flatten() {
+            let Some(url) = advisory["url"].as_str() else {
+                continue;
+            };
+            let id = url.rsplit('/').next().unwrap_or(url);
+            vulnerabilities.push((id.to_string(), format!("{} in {}", id, package)));
+        }
+    }
+    vulnerabilities
+}
+
+// pip-audit's JSON report has no severity, every vulnerability is returned
+fn pip_audit(dir: &Path) -> Vec<(String, String)> {
+    // pip-audit reads requirements, not Pipfile.lock
+    let lock: Value = serde_json::from_str(
+        &fs::read_to_string(dir.join("Pipfile.lock")).expect("Unable to read Pipfile.lock"),
+    )
+    .expect("Unable to parse Pipfile.lock");
+    let requirements = lock["default"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, package)| Some(format!("{}{}\n", name, package["version"].as_str()?)))
+        .collect::<String>();
+    let requirements_file = dir.join(".pkgbuilder-audit-requirements.txt");
+    fs::write(&requirements_file, requirements).expect("Unable to write requirements");
+
+    let report = run_json(
+        Command::new("pip-audit")
+            .arg("--format")
+            .arg("json")
+            .arg("--no-deps")
+            .arg("--disable-pip")
+            .arg("--requirement")
+            .arg(&requirements_file),
+        "pip-audit",
+        dir,
+    );
+    fs::remove_file(&requirements_file).expect("Unable to remove requirements");
+
+    let mut vulnerabilities = Vec::new();
+    for dependency in report["dependencies"].as_array().into_iter().flatten() {
+        let package = dependency["name"].as_str().unwrap_or_default();
+        for vulnerability in dependency["vulns"].as_array().into_iter().flatten() {
+            let id = vulnerability["id"].as_str().unwrap_or_default();
+            vulnerabilities.push((id.to_string(), format!("{} in {}", id, package)));
+        }
+    }
+    vulnerabilities
+}
+
+// audit tools exit nonzero when they find vulnerabilities,
+// so only the report on stdout is used
+fn run_json(command: &mut Command, tool: &str, dir: &Path) -> Value {
+    if !util::command_exists(tool) {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: tool.to_string()
+            }
+        );
+    }
+
+    println!("Auditing dependencies in {} with {}", dir.display(), tool);
+    let output = command
+        .current_dir(dir)
+        .output()
+        .expect("Failed to execute command");
+
+    serde_json::from_slice(&output.stdout).unwrap_or_else(|_| {
+        panic!(
+            "Unable to read the {} report: {}",
+            tool,
+            String::from_utf8_lossy(&output.stderr)
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cvss_base_scores() {
+        let score = |metrics: &str| cvss_base_score(&format!("CVSS:3.1/{}", metrics));
+        assert_eq!(score("AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        assert_eq!(score("AV:N/AC:L/PR:N/UI:N/S:C/C:H/I:H/A:H"), Some(10.0));
+        assert_eq!(score("AV:N/AC:L/PR:L/UI:N/S:C/C:L/I:L/A:N"), Some(6.4));
+        assert_eq!(score("AV:N/AC:L/PR:N/UI:R/S:U/C:L/I:N/A:N"), Some(4.3));
+        assert_eq!(score("AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:N"), Some(0.0));
+        assert_eq!(cvss_base_score("CVSS:4.0/AV:N/AC:L"), None);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{cli, repo, try_read_pkgfile, PkgFile};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    path::Path,
+    process::Command,
+    sync::mpsc,
+    thread,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+// pkgbuilder build-batch <PKGFILE_DIR> --output <OUT_DIR> [--jobs <N>]
+//
+// Builds every PkgFile in a directory in dependency order.
+// Each package is built by a separate pkgbuilder process so a failing
+// package only skips the packages depending on it.
+pub fn run(mut args: Vec<String>) {
+    let output_path = cli::take_option(&mut args, "--output").expect("No output path provided");
+    let jobs = cli::take_option(&mut args, "--jobs")
+        .map(|jobs| jobs.parse::<usize>().expect("Invalid number of jobs"))
+        .unwrap_or(1)
+        .max(1);
+    let pkgfile_dir = args.first().expect("No PkgFile directory provided");
+
+    let mut pkgfile_paths = fs::read_dir(pkgfile_dir)
+        .expect("Unable to read PkgFile directory")
+        .map(|entry| entry.expect("Unable to read directory entry").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    pkgfile_paths.sort();
+
+    // an invalid PkgFile fails without stopping the batch,
+    // the packages depending on it are skipped
+    let pkgfiles = pkgfile_paths
+        .iter()
+        .map(|path| match try_read_pkgfile(path, None) {
+            Ok(pkgfile) => Some(pkgfile),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    let names = pkgfiles
+        .iter()
+        .zip(&pkgfile_paths)
+        .map(|(pkgfile, path)| match pkgfile {
+            Some(pkgfile) => pkgfile.package.name.clone(),
+            None => declared_name(path),
+        })
+        .collect::<Vec<_>>();
+    let dependencies = dependency_graph(&pkgfiles, &names);
+
+    let mut states = pkgfiles
+        .iter()
+        .map(|pkgfile| match pkgfile {
+            Some(_) => State::Pending,
+            None => State::Failed,
+        })
+        .collect::<Vec<_>>();
+    let (sender, receiver) = mpsc::channel();
+    let mut running = 0;
+
+    loop {
+        // packages depending on a failed or skipped package are skipped
+        for index in 0..pkgfiles.len() {
+            if states[index] == State::Pending
+                && dependencies[index]
+                    .iter()
+                    .any(|dependency| matches!(states[*dependency], State::Failed | State::Skipped))
+            {
+                println!("Skipping {}, a dependency failed", names[index]);
+                states[index] = State::Skipped;
+            }
+        }
+
+        let ready = (0..pkgfiles.len())
+            .filter(|index| states[*index] == State::Pending)
+            .filter(|index| {
+                dependencies[*index]
+                    .iter()
+                    .all(|dependency| states[*dependency] == State::Succeeded)
+            })
+            .collect::<Vec<_>>();
+
+        for index in ready.into_iter().take(jobs - running) {
+            states[index] = State::Running;
+            running += 1;
+
+            println!("Building {}", names[index]);
+            let pkgfile_path = pkgfile_paths[index].clone();
+            let output_path = output_path.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let success = Command::new(env::current_exe().expect("Unable to find pkgbuilder"))
+                    .arg(&pkgfile_path)
+                    .arg(&output_path)
+                    .arg("--no-index")
+                    .status()
+                    .map(|status| status.success())
+                    .unwrap_or(false);
+                sender
+                    .send((index, success))
+                    .expect("Unable to report build result");
+            });
+        }
+
+        if running == 0 {
+            break;
+        }
+
+        let (index, success) = receiver.recv().expect("Unable to receive build result");
+        running -= 1;
+        states[index] = if success {
+            State::Succeeded
+        } else {
+            State::Failed
+        };
+    }
+
+    // anything still pending depends on itself through a cycle
+    for index in 0..pkgfiles.len() {
+        if states[index] == State::Pending {
+            eprintln!(
+                "Skipping {}, it is part of a dependency cycle",
+                names[index]
+            );
+            states[index] = State::Skipped;
+        }
+    }
+
+    write_indexes(&output_path);
+
+    println!("Batch build summary:");
+    let mut failed = false;
+    for (name, state) in names.iter().zip(&states) {
+        let state = match state {
+            State::Succeeded => "succeeded",
+            State::Failed => "failed",
+            _ => "skipped",
+        };
+        println!("  {}: {}", name, state);
+        failed |= state != "succeeded";
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// the builds run in parallel and don't touch PACKAGES, the index of the
+// output directory and of its architecture subdirectories is written here
+fn write_indexes(output_path: &str) {
+    if !Path::new(output_path).is_dir() {
+        return;
+    }
+
+    let arch_dirs = fs::read_dir(output_path)
+        .expect("Unable to read output directory")
+        .map(|entry| entry.expect("Unable to read directory entry").path())
+        .filter(|path| path.is_dir())
+        .map(|path| path.to_string_lossy().to_string())
+        .filter(|dir| !repo::list_tarballs(dir).is_empty())
+        .collect::<Vec<_>>();
+
+    for dir in std::iter::once(output_path).chain(arch_dirs.iter().map(String::as_str)) {
+        if !repo::list_tarballs(dir).is_empty() {
+            repo::write_index(dir, &repo::generate_index(dir));
+        }
+    }
+    if !arch_dirs.is_empty() {
+        repo::write_repo_toml(output_path);
+    }
+}
+
+// for every PkgFile the indices of the PkgFiles it depends on,
+// dependencies on packages outside the batch are ignored,
+// a PkgFile depending on its own packages doesn't depend on itself.
+// invalid PkgFiles only provide their name and depend on nothing
+fn dependency_graph(pkgfiles: &[Option<PkgFile>], names: &[String]) -> Vec<Vec<usize>> {
+    let sources = pkgfiles
+        .iter()
+        .zip(names)
+        .map(|(pkgfile, name)| {
+            pkgfile
+                .as_ref()
+                .and_then(|pkgfile| pkgfile.package.source_package.as_deref())
+                .unwrap_or(name)
+        })
+        .collect::<Vec<_>>();
+
+    // subpackages are built by the PkgFile declaring them,
+    // a dependency can also name the source package
+    let mut providers: HashMap<&str, usize> = HashMap::new();
+    for (index, pkgfile) in pkgfiles.iter().enumerate() {
+        providers.entry(sources[index]).or_insert(index);
+        providers.insert(&names[index], index);
+        for subpackage in pkgfile
+            .iter()
+            .flat_map(|pkgfile| pkgfile.subpackage.iter().flatten())
+        {
+            providers.insert(&subpackage.name, index);
+        }
+    }
+
+    pkgfiles
+        .iter()
+        .enumerate()
+        .map(|(index, pkgfile)| {
+            let mut dependencies = BTreeMap::new();
+            let names = pkgfile.iter().flat_map(|pkgfile| {
+                pkgfile
+                    .package
+                    .depends
+                    .iter()
+                    .flatten()
+                    .chain(pkgfile.package.makedepends.iter().flatten())
+            });
+            for name in names {
+                if let Some(provider) = providers.get(name.as_str()) {
+                    if *provider != index {
+                        dependencies.insert(*provider, ());
+                    }
+                }
+            }
+            dependencies.into_keys().collect()
+        })
+        .collect()
+}
+
+// the package name of a PkgFile that can't be read as a whole,
+// taken from [package] if the TOML parses, otherwise the file name
+fn declared_name(path: &str) -> String {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| toml::from_str::<toml::Table>(&contents).ok())
+        .and_then(|pkgfile| {
+            pkgfile
+                .get("package")?
+                .get("name")?
+                .as_str()
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| {
+            Path::new(path)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+}
@@ -0,0 +1,589 @@
+use crate::{
+    config::GlobalConfig, util, PkgBuilderError, PkgFile, PkgFileBuild, PkgFilePythonFlags,
+};
+use std::{
+    collections::BTreeMap, env, fs, os::unix::fs::PermissionsExt, path::Path, process::Command,
+};
+
+// Computes the environment the build script is started with.
+// It is based on the environment of pkgbuilder itself.
+pub fn build_environment(
+    pkgfile: &PkgFile,
+    build: &PkgFileBuild,
+    build_dir: &str,
+    out_dir: &str,
+    source_date_epoch: u64,
+) -> BTreeMap<String, String> {
+    // variables that aren't valid UTF-8 can't be passed on and are skipped
+    let mut vars: BTreeMap<String, String> = env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+
+    vars.insert("OUT".to_string(), out_dir.to_string());
+    vars.insert(
+        "SOURCE_DATE_EPOCH".to_string(),
+        source_date_epoch.to_string(),
+    );
+    vars.insert("BUILD_DATE".to_string(), iso_8601(source_date_epoch));
+
+    // SOURCE_DIR_<N> is the directory the Nth source was placed in
+    for (index, source) in pkgfile.source.iter().flatten().enumerate() {
+        vars.insert(
+            format!("SOURCE_DIR_{}", index),
+            source.destination_dir(build_dir),
+        );
+    }
+
+    if build.hermetic.unwrap_or(false) {
+        setup_hermetic_path(pkgfile, build, &mut vars);
+    }
+
+    if build.distcc.unwrap_or(false) {
+        setup_distcc(build, &mut vars);
+    }
+
+    if build.sccache.unwrap_or(false) {
+        setup_sccache(build, build_dir, &mut vars);
+    }
+
+    if let Some(sanitizer) = sanitizer(build) {
+        setup_sanitizer(sanitizer, &mut vars);
+    }
+
+    if build.coverage.unwrap_or(false) {
+        setup_coverage(build_dir, &mut vars);
+    }
+
+    if build.iwyu.unwrap_or(false) {
+        setup_iwyu(build_dir, &mut vars);
+    }
+
+    if let Some(ref ld_preload) = build.ld_preload {
+        setup_ld_preload(ld_preload, &mut vars);
+    }
+
+    if build.no_build_id.unwrap_or(false) {
+        append_var(&mut vars, "LDFLAGS", "-Wl,--build-id=none", " ");
+    }
+
+    // prefer system libraries over copies bundled with the sources
+    if !build.use_vendored_libs.unwrap_or(false) {
+        append_var(&mut vars, "CONFIGURE_FLAGS", "--disable-embedded-libs", " ");
+        vars.insert(
+            "CMAKE_FIND_USE_SYSTEM_ENVIRONMENT_PATH".to_string(),
+            "ON".to_string(),
+        );
+    }
+
+    let install_prefix = build.install_prefix.as_deref().unwrap_or("/usr");
+    vars.insert(
+        "CMAKE_INSTALL_PREFIX".to_string(),
+        install_prefix.to_string(),
+    );
+    vars.insert("MESON_PREFIX".to_string(), install_prefix.to_string());
+
+    if let Some(ref cmake_flags) = build.cmake_flags {
+        let mut args = define_args(cmake_flags);
+        if !cmake_flags.contains_key("CMAKE_INSTALL_PREFIX") {
+            args.insert(0, format!("-DCMAKE_INSTALL_PREFIX={}", install_prefix));
+        }
+        vars.insert("CMAKE_EXTRA_ARGS".to_string(), args.join(" "));
+    }
+
+    if let Some(ref meson_flags) = build.meson_flags {
+        vars.insert(
+            "MESON_EXTRA_ARGS".to_string(),
+            define_args(meson_flags).join(" "),
+        );
+    }
+
+    setup_python_interpreter(build, build_dir, &mut vars);
+
+    if let Some(ref python_flags) = build.python_flags {
+        setup_python(python_flags, out_dir, &mut vars);
+    }
+
+    let deny_list = env_deny_list(build);
+    vars.retain(|name, _| {
+        let denied = deny_list
+            .iter()
+            .any(|pattern| util::glob_match(pattern, name));
+        if denied {
+            eprintln!("Warning: removed {} from the build environment", name);
+        }
+        !denied
+    });
+
+    vars
+}
+
+// patterns of variables kept out of the build environment,
+// default_env_deny_list of the global config and build.host_env_deny_list
+pub fn env_deny_list(build: &PkgFileBuild) -> Vec<String> {
+    GlobalConfig::load()
+        .default_env_deny_list
+        .into_iter()
+        .flatten()
+        .chain(build.host_env_deny_list.iter().flatten().cloned())
+        .collect()
+}
+
+// bash unsetting the denied variables again, for scripts sourcing
+// the host's .bashrc which can export them
+pub fn unset_denied_vars(deny_list: &[String]) -> String {
+    if deny_list.is_empty() {
+        return String::new();
+    }
+
+    // only * and ? are wildcards, like in util::glob_match
+    let patterns = deny_list
+        .iter()
+        .map(|pattern| {
+            pattern
+                .chars()
+                .map(|c| match c {
+                    '*' | '?' => c.to_string(),
+                    _ => format!("\\{}", c),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+
+    format!(
+        "for __pkgbuilder_var in $(compgen -v); do\n    case \"$__pkgbuilder_var\" in {}) unset \"$__pkgbuilder_var\" 2>/dev/null ;; esac\ndone\nunset __pkgbuilder_var\n\n",
+        patterns
+    )
+}
+
+// the PATH of a hermetic build, the system directories plus the directories
+// makedepends are found in on the host and hermetic_extra_paths
+const HERMETIC_PATH: [&str; 4] = ["/usr/bin", "/bin", "/usr/sbin", "/sbin"];
+
+fn setup_hermetic_path(
+    pkgfile: &PkgFile,
+    build: &PkgFileBuild,
+    vars: &mut BTreeMap<String, String>,
+) {
+    let host_path = vars.get("PATH").cloned().unwrap_or_default();
+    let mut path = HERMETIC_PATH
+        .iter()
+        .map(|dir| dir.to_string())
+        .collect::<Vec<_>>();
+
+    for tool in pkgfile.package.makedepends.iter().flatten() {
+        let dir = host_path
+            .split(':')
+            .find(|dir| !dir.is_empty() && Path::new(dir).join(tool).is_file());
+        match dir {
+            Some(dir) if !path.iter().any(|existing| existing == dir) => path.push(dir.to_string()),
+            Some(_) => {}
+            None => eprintln!(
+                "Warning: {} is not in PATH, it is not available in the hermetic build",
+                tool
+            ),
+        }
+    }
+
+    for dir in build.hermetic_extra_paths.iter().flatten() {
+        if !path.contains(dir) {
+            path.push(dir.clone());
+        }
+    }
+
+    eprintln!("Hermetic PATH: {}", path.join(":"));
+    vars.insert("PATH".to_string(), path.join(":"));
+}
+
+// explains a failed script of a hermetic build, most likely a tool outside the hermetic PATH
+pub fn hermetic_failure_hint(build: &PkgFileBuild, vars: &BTreeMap<String, String>) {
+    if !build.hermetic.unwrap_or(false) {
+        return;
+    }
+
+    eprintln!(
+        "The build is hermetic, a tool it needs may be missing from PATH={}",
+        vars.get("PATH").map(String::as_str).unwrap_or_default()
+    );
+    eprintln!(
+        "The original PATH was {}, add the directory to hermetic_extra_paths",
+        env::var("PATH").unwrap_or_default()
+    );
+}
+
+// libraries that break the build in non-obvious ways when they are preloaded
+const SYSTEM_LIBRARIES: [&str; 2] = ["libc.so", "libpthread.so"];
+
+// sets LD_PRELOAD, library names are resolved with the ldconfig cache
+fn setup_ld_preload(libraries: &[String], vars: &mut BTreeMap<String, String>) {
+    let mut paths = Vec::new();
+    for library in libraries {
+        let path = if library.starts_with('/') {
+            Some(library.clone())
+        } else {
+            resolve_library(library)
+        };
+        let path = match path {
+            Some(path) if Path::new(&path).exists() => path,
+            _ => panic!(
+                "{}",
+                PkgBuilderError::LibraryNotFound {
+                    name: library.to_string()
+                }
+            ),
+        };
+
+        let file_name = path.rsplit('/').next().unwrap_or(&path);
+        if SYSTEM_LIBRARIES
+            .iter()
+            .any(|system| file_name.starts_with(system))
+        {
+            eprintln!(
+                "Warning: preloading {} shadows a system library and may break the build",
+                path
+            );
+        }
+
+        paths.push(path);
+    }
+
+    eprintln!("Preloading {}", paths.join(":"));
+    vars.insert("LD_PRELOAD".to_string(), paths.join(":"));
+}
+
+// finds a library by its name, e.g. libfoo.so.1 or libfoo, in `ldconfig -p`
+fn resolve_library(name: &str) -> Option<String> {
+    let output = Command::new("ldconfig")
+        .arg("-p")
+        .output()
+        .expect("Failed to execute command");
+    if !output.status.success() {
+        return None;
+    }
+
+    //	libfoo.so.1 (libc6,x86-64) => /usr/lib/libfoo.so.1
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (soname, path) = line.trim().split_once(" => ")?;
+            let soname = soname.split_whitespace().next()?;
+            let matches = soname == name
+                || soname
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with('.'));
+            matches.then(|| path.to_string())
+        })
+        .next()
+}
+
+// pins the interpreter build systems find with python_path
+// and keeps the host's python customizations out of the build
+fn setup_python_interpreter(
+    build: &PkgFileBuild,
+    build_dir: &str,
+    vars: &mut BTreeMap<String, String>,
+) {
+    if !build.use_system_python.unwrap_or(true) {
+        for name in ["PYTHONPATH", "PYTHONSTARTUP"] {
+            if vars.remove(name).is_some() {
+                eprintln!("Unset {} for the build", name);
+            }
+        }
+    }
+
+    let Some(ref python) = build.python_path else {
+        return;
+    };
+    if !Path::new(python).is_file() {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: python.clone()
+            }
+        );
+    }
+
+    vars.insert("PYTHON".to_string(), python.clone());
+    vars.insert("PYTHON3".to_string(), python.clone());
+
+    // tools looking up python3 in PATH find the same interpreter,
+    // the directory is removed with the build directory
+    let python_dir = format!("{}.python", build_dir);
+    fs::create_dir_all(&python_dir).expect("Unable to create python directory");
+    for name in ["python3", "python"] {
+        let link = format!("{}/{}", python_dir, name);
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(python, &link).expect("Unable to link python");
+    }
+    prepend_path(vars, &python_dir);
+    eprintln!("Using {} as python3", python);
+}
+
+fn setup_python(
+    python_flags: &PkgFilePythonFlags,
+    out_dir: &str,
+    vars: &mut BTreeMap<String, String>,
+) {
+    vars.insert("PIP_PREFIX".to_string(), out_dir.to_string());
+    vars.insert("PYTHONHASHSEED".to_string(), "0".to_string());
+
+    if let Some(ref site_packages_dir) = python_flags.site_packages_dir {
+        append_var(vars, "PYTHONPATH", site_packages_dir, ":");
+    }
+
+    if !python_flags.write_bytecode.unwrap_or(true) {
+        vars.insert("PYTHONDONTWRITEBYTECODE".to_string(), "1".to_string());
+    }
+
+    if let Some(ref pip_args) = python_flags.pip_args {
+        append_var(vars, "PIP_ARGS", &pip_args.join(" "), " ");
+    }
+}
+
+fn append_var(vars: &mut BTreeMap<String, String>, name: &str, value: &str, separator: &str) {
+    let value = match vars.get(name) {
+        Some(existing) if !existing.is_empty() => format!("{}{}{}", existing, separator, value),
+        _ => value.to_string(),
+    };
+    vars.insert(name.to_string(), value);
+}
+
+// Resolves the timestamp of the build, in order of precedence:
+// --force-timestamp, package.source_date_epoch,
+// the last commit of the first git source with --reproducible
+// and the current time
+pub fn source_date_epoch(
+    pkgfile: &PkgFile,
+    build_dir: &str,
+    reproducible: bool,
+    force_timestamp: Option<u64>,
+) -> u64 {
+    if let Some(timestamp) = force_timestamp.or(pkgfile.package.source_date_epoch) {
+        return timestamp;
+    }
+
+    if reproducible {
+        let git_source = pkgfile
+            .source
+            .iter()
+            .flatten()
+            .find(|source| source.source.ends_with(".git"));
+
+        if let Some(source) = git_source {
+            let output = Command::new("git")
+                .arg("log")
+                .arg("-1")
+                .arg("--format=%ct")
+                .current_dir(source.destination_dir(build_dir))
+                .output()
+                .expect("Failed to execute command");
+
+            match String::from_utf8_lossy(&output.stdout).trim().parse() {
+                Ok(timestamp) if output.status.success() => return timestamp,
+                _ => eprintln!(
+                    "Warning: unable to read the commit timestamp of {}",
+                    source.source
+                ),
+            }
+        } else {
+            eprintln!(
+                "Warning: no git source to derive SOURCE_DATE_EPOCH from, using the current time"
+            );
+        }
+    }
+
+    chrono::Utc::now().timestamp() as u64
+}
+
+pub fn iso_8601(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .expect("Invalid timestamp")
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+fn setup_distcc(build: &PkgFileBuild, vars: &mut BTreeMap<String, String>) {
+    let hosts = match build.distcc_hosts {
+        Some(ref hosts) if !hosts.is_empty() => Some(hosts.join(" ")),
+        _ => vars.get("DISTCC_HOSTS").cloned(),
+    };
+
+    let Some(hosts) = hosts else {
+        eprintln!("Warning: distcc is enabled but no hosts are configured, compiling locally");
+        return;
+    };
+
+    prepend_path(vars, "/usr/lib/distcc/bin");
+    vars.insert("DISTCC_HOSTS".to_string(), hosts);
+
+    if let Some(jobs) = build.distcc_jobs {
+        vars.insert("DISTCC_PARALLELISM".to_string(), jobs.to_string());
+    }
+}
+
+// formats build system options as -D<KEY>=<VALUE> arguments
+fn define_args(flags: &BTreeMap<String, String>) -> Vec<String> {
+    flags
+        .iter()
+        .map(|(key, value)| format!("-D{}={}", key, value))
+        .collect()
+}
+
+// CC, CXX and RUSTC_WRAPPER use sccache, wrappers first in PATH catch
+// build systems calling cc, c++, gcc or g++ directly
+fn setup_sccache(build: &PkgFileBuild, build_dir: &str, vars: &mut BTreeMap<String, String>) {
+    if !util::command_exists("sccache") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "sccache".to_string()
+            }
+        );
+    }
+
+    vars.insert("RUSTC_WRAPPER".to_string(), "sccache".to_string());
+    vars.insert("CC".to_string(), "sccache gcc".to_string());
+    vars.insert("CXX".to_string(), "sccache g++".to_string());
+
+    let sccache_dir = format!("{}.sccache", build_dir);
+    fs::create_dir_all(&sccache_dir).expect("Unable to create sccache directory");
+    for compiler in ["cc", "c++", "gcc", "g++"] {
+        // the wrappers shadow the compilers in PATH, so they are resolved beforehand
+        let script = format!(
+            "#!/bin/sh\nexec sccache {} \"$@\"\n",
+            resolve_command(vars, compiler)
+        );
+        let path = format!("{}/{}", sccache_dir, compiler);
+        fs::write(&path, script).expect("Unable to write compiler wrapper");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .expect("Unable to make compiler wrapper executable");
+    }
+    prepend_path(vars, &sccache_dir);
+
+    if let Some(ref bucket) = build.sccache_bucket {
+        vars.insert("SCCACHE_BUCKET".to_string(), bucket.clone());
+        if let Some(ref region) = build.sccache_region {
+            vars.insert("SCCACHE_REGION".to_string(), region.clone());
+        }
+    }
+}
+
+// the sanitizer to build with, None if disabled
+pub fn sanitizer(build: &PkgFileBuild) -> Option<&str> {
+    match build.sanitizer.as_deref() {
+        None | Some("none") => None,
+        Some(sanitizer @ ("address" | "thread" | "memory" | "undefined")) => Some(sanitizer),
+        Some(sanitizer) => panic!(
+            "{}",
+            PkgBuilderError::UnknownSanitizer {
+                sanitizer: sanitizer.to_string()
+            }
+        ),
+    }
+}
+
+fn setup_sanitizer(sanitizer: &str, vars: &mut BTreeMap<String, String>) {
+    let flag = format!("-fsanitize={}", sanitizer);
+    for name in ["CFLAGS", "CXXFLAGS", "LDFLAGS"] {
+        append_var(vars, name, &flag, " ");
+    }
+    append_var(
+        vars,
+        "RUSTFLAGS",
+        &format!("-Zsanitizer={}", sanitizer),
+        " ",
+    );
+    vars.insert("ASAN_OPTIONS".to_string(), "detect_leaks=1".to_string());
+}
+
+// whether the C compiler of the build is clang rather than gcc
+pub fn uses_clang(vars: &BTreeMap<String, String>) -> bool {
+    vars.get("CC").is_some_and(|cc| cc.contains("clang"))
+}
+
+fn setup_coverage(build_dir: &str, vars: &mut BTreeMap<String, String>) {
+    eprintln!("Warning: coverage builds are slow and should not be distributed");
+
+    let flags = if uses_clang(vars) {
+        // raw profiles are written into the build directory and merged afterwards
+        vars.insert(
+            "LLVM_PROFILE_FILE".to_string(),
+            format!("{}/coverage-%p.profraw", build_dir),
+        );
+        "-fprofile-instr-generate -fcoverage-mapping"
+    } else {
+        "--coverage"
+    };
+
+    for name in ["CFLAGS", "CXXFLAGS", "LDFLAGS"] {
+        append_var(vars, name, flags, " ");
+    }
+}
+
+// puts cc and c++ wrappers first in PATH and in CC and CXX, they run
+// include-what-you-use on every source they compile before the real compiler
+fn setup_iwyu(build_dir: &str, vars: &mut BTreeMap<String, String>) {
+    if !util::command_exists("include-what-you-use") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "include-what-you-use".to_string()
+            }
+        );
+    }
+    eprintln!("Warning: include-what-you-use builds are slow and should not be distributed");
+
+    let iwyu_dir = format!("{}.iwyu", build_dir);
+    fs::create_dir_all(&iwyu_dir).expect("Unable to create iwyu directory");
+
+    for (wrapper, name, default) in [("cc", "CC", "cc"), ("c++", "CXX", "c++")] {
+        let compiler = vars.get(name).cloned().unwrap_or(default.to_string());
+        // the wrappers shadow cc and c++ in PATH, so the compiler is resolved beforehand
+        let compiler = if compiler.contains(' ') || compiler.contains('/') {
+            compiler
+        } else {
+            resolve_command(vars, &compiler)
+        };
+
+        let script = format!(
+            "#!/bin/sh\n\
+             for arg in \"$@\"; do\n\
+             \tcase \"$arg\" in\n\
+             \t*.c|*.cc|*.cpp|*.cxx|*.C)\n\
+             \t\tinclude-what-you-use \"$@\" 2>> {dir}/iwyu.log\n\
+             \t\tbreak\n\
+             \t\t;;\n\
+             \tesac\n\
+             done\n\
+             exec {compiler} \"$@\"\n",
+            dir = iwyu_dir,
+            compiler = compiler
+        );
+        let path = format!("{}/{}", iwyu_dir, wrapper);
+        fs::write(&path, script).expect("Unable to write compiler wrapper");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .expect("Unable to make compiler wrapper executable");
+        vars.insert(name.to_string(), path);
+    }
+
+    prepend_path(vars, &iwyu_dir);
+}
+
+// the path of a command in the PATH of the build, the name if it isn't found
+fn resolve_command(vars: &BTreeMap<String, String>, name: &str) -> String {
+    vars.get("PATH")
+        .into_iter()
+        .flat_map(|path| path.split(':'))
+        .map(|dir| Path::new(dir).join(name))
+        .find(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or(name.to_string())
+}
+
+fn prepend_path(vars: &mut BTreeMap<String, String>, dir: &str) {
+    let path = match vars.get("PATH") {
+        Some(path) if !path.is_empty() => format!("{}:{}", dir, path),
+        _ => dir.to_string(),
+    };
+    vars.insert("PATH".to_string(), path);
+}
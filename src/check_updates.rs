@@ -0,0 +1,62 @@
+use crate::{cli, read_pkgfile, repo};
+use std::{cmp::Ordering, fs};
+
+// pkgbuilder check-updates <PKGFILE_DIR> --repo <REPO_DIR>
+//
+// Lists packages where the repository has a newer build
+// than the PkgFile in PKGFILE_DIR.
+pub fn run(mut args: Vec<String>) {
+    let repo_dir = cli::take_option(&mut args, "--repo").expect("No repository path provided");
+    let pkgfile_dir = args.first().expect("No PkgFile directory provided");
+
+    let index = repo::read_index(&repo_dir);
+
+    let mut pkgfile_paths = fs::read_dir(pkgfile_dir)
+        .expect("Unable to read PkgFile directory")
+        .map(|entry| entry.expect("Unable to read directory entry").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        })
+        .collect::<Vec<_>>();
+    pkgfile_paths.sort();
+
+    let mut updates = 0;
+    for pkgfile_path in pkgfile_paths {
+        let pkgfile = read_pkgfile(&pkgfile_path.to_string_lossy());
+        let package = &pkgfile.package;
+
+        // the index may contain several builds of the same package, use the newest one
+        let newest = index
+            .package
+            .iter()
+            .filter(|entry| entry.name == package.name)
+            .max_by(|a, b| repo::compare_releases(&a.version, a.pkgrel, &b.version, b.pkgrel));
+
+        let Some(newest) = newest else {
+            continue;
+        };
+
+        if repo::compare_releases(
+            &newest.version,
+            newest.pkgrel,
+            &package.version,
+            package.pkgrel,
+        ) == Ordering::Greater
+        {
+            println!(
+                "{}: {}-{} -> {}-{}",
+                package.name,
+                package.version,
+                package.pkgrel.unwrap_or(1),
+                newest.version,
+                newest.pkgrel.unwrap_or(1)
+            );
+            updates += 1;
+        }
+    }
+
+    if updates == 0 {
+        println!("All packages are up to date");
+    }
+}
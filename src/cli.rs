@@ -0,0 +1,34 @@
+// Minimal helpers for parsing subcommand arguments.
+// Flags are removed from the argument list as they are consumed,
+// whatever is left over are the positional arguments.
+
+// removes a boolean flag like `--list` and returns whether it was present
+pub fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+// removes an option with a value like `--repo <DIR>` or `--repo=<DIR>`
+// and returns its value
+pub fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let index = args
+        .iter()
+        .position(|arg| arg == name || arg.starts_with(&prefix))?;
+
+    let arg = args.remove(index);
+    if let Some(value) = arg.strip_prefix(&prefix) {
+        return Some(value.to_string());
+    }
+
+    if index < args.len() {
+        Some(args.remove(index))
+    } else {
+        panic!("Missing value for {}", name);
+    }
+}
@@ -0,0 +1,49 @@
+use serde::Deserialize;
+use std::{env, fs, path::PathBuf};
+
+// Global configuration from ~/.config/pkgbuilder/config.toml
+#[derive(Debug, Default, Deserialize)]
+pub struct GlobalConfig {
+    // key from the pkgbuilder keystore used for signing
+    pub signing_key_id: Option<String>,
+    pub profile: Option<GlobalProfileConfig>,
+    // variables removed from the environment of every build, like host_env_deny_list
+    pub default_env_deny_list: Option<Vec<String>>,
+    // md5, sha1, sha256 (default) or sha512, used by update-checksums
+    pub default_checksum_algorithm: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GlobalProfileConfig {
+    // build profile used when --profile is not given
+    pub default: Option<String>,
+}
+
+impl GlobalConfig {
+    // loads the global configuration, a missing file is an empty configuration
+    pub fn load() -> GlobalConfig {
+        let path = config_dir().join("config.toml");
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).expect("Unable to parse the global config"),
+            Err(_) => GlobalConfig::default(),
+        }
+    }
+}
+
+pub fn cache_dir() -> PathBuf {
+    match env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("pkgbuilder"),
+        _ => PathBuf::from(env::var("HOME").expect("HOME is not set"))
+            .join(".cache")
+            .join("pkgbuilder"),
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("pkgbuilder"),
+        _ => PathBuf::from(env::var("HOME").expect("HOME is not set"))
+            .join(".config")
+            .join("pkgbuilder"),
+    }
+}
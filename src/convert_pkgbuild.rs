@@ -0,0 +1,308 @@
+use crate::cli;
+use std::{collections::BTreeMap, fs, process::Command};
+use toml_edit::{value, Array, ArrayOfTables, Document, Item, Table};
+
+// sources PKGBUILD and prints every variable as <name>\0<count>\0<values>...\0,
+// functions are printed with their body only
+const EXTRACT_SCRIPT: &str = r#"
+set -e
+source "$1"
+
+emit() {
+    local name=$1
+    shift
+    printf '%s\0%s\0' "$name" "$#"
+    [ "$#" -eq 0 ] || printf '%s\0' "$@"
+}
+
+for variable in pkgname pkgver pkgrel epoch pkgdesc url license depends makedepends \
+    checkdepends optdepends provides conflicts replaces backup options install changelog \
+    arch source noextract validpgpkeys md5sums sha1sums sha256sums sha512sums b2sums; do
+    if declare -p "$variable" >/dev/null 2>&1; then
+        declare -n values=$variable
+        emit "$variable" "${values[@]}"
+        unset -n values
+    fi
+done
+
+for function in prepare build check package; do
+    if declare -F "$function" >/dev/null; then
+        emit "$function()" "$(declare -f "$function" | sed '1,2d;$d')"
+    fi
+done
+"#;
+
+// PKGBUILD variables without an equivalent in the PkgFile format
+const UNSUPPORTED: [&str; 11] = [
+    "epoch",
+    "checkdepends",
+    "optdepends",
+    "conflicts",
+    "replaces",
+    "options",
+    "install",
+    "changelog",
+    "noextract",
+    "validpgpkeys",
+    "b2sums",
+];
+
+// checksum arrays in order of preference, the algorithm is detected from the length
+const CHECKSUMS: [&str; 4] = ["sha256sums", "sha512sums", "sha1sums", "md5sums"];
+
+// pkgbuilder convert-pkgbuild <PKGBUILD_PATH> [--output <FILE>]
+//
+// Converts an Arch Linux PKGBUILD into a PkgFile.
+// Features without an equivalent are reported as warnings.
+pub fn run(mut args: Vec<String>) {
+    let output_file = cli::take_option(&mut args, "--output");
+    let pkgbuild_path = args.first().expect("No PKGBUILD provided");
+
+    let output = Command::new("bash")
+        .arg("-c")
+        .arg(EXTRACT_SCRIPT)
+        .arg("bash")
+        .arg(pkgbuild_path)
+        .output()
+        .expect("Failed to execute command");
+    if !output.status.success() {
+        panic!(
+            "Unable to source {}: {}",
+            pkgbuild_path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let variables = parse_variables(&String::from_utf8_lossy(&output.stdout));
+    let pkgfile = convert(&variables);
+
+    match output_file {
+        Some(output_file) => {
+            fs::write(&output_file, pkgfile).expect("Unable to write the PkgFile");
+            println!("Wrote {}", output_file);
+        }
+        None => print!("{}", pkgfile),
+    }
+}
+
+fn parse_variables(output: &str) -> BTreeMap<String, Vec<String>> {
+    let mut fields = output.split('\0');
+    let mut variables = BTreeMap::new();
+
+    while let Some(name) = fields.next().filter(|name| !name.is_empty()) {
+        let count = fields
+            .next()
+            .and_then(|count| count.parse::<usize>().ok())
+            .expect("Unable to read the PKGBUILD variables");
+        let values = fields
+            .by_ref()
+            .take(count)
+            .map(|value| value.to_string())
+            .collect();
+        variables.insert(name.to_string(), values);
+    }
+
+    variables
+}
+
+fn convert(variables: &BTreeMap<String, Vec<String>>) -> String {
+    let get = |name: &str| variables.get(name).cloned().unwrap_or_default();
+    let first = |name: &str| get(name).into_iter().next();
+
+    for name in UNSUPPORTED {
+        if !get(name).is_empty() {
+            eprintln!("Warning: {} has no PkgFile equivalent, ignoring it", name);
+        }
+    }
+
+    let mut document = Document::new();
+
+    // package
+    let mut package = Table::new();
+    let names = get("pkgname");
+    if names.len() > 1 {
+        eprintln!(
+            "Warning: split packages are not converted, only {} is",
+            names[0]
+        );
+    }
+    package["name"] = value(names.first().cloned().unwrap_or_default());
+    package["version"] = value(first("pkgver").unwrap_or_default());
+    if let Some(pkgrel) = first("pkgrel") {
+        match pkgrel.parse::<i64>() {
+            Ok(pkgrel) => package["pkgrel"] = value(pkgrel),
+            Err(_) => eprintln!(
+                "Warning: pkgrel {} is not a whole number, ignoring it",
+                pkgrel
+            ),
+        }
+    }
+    package["description"] = value(first("pkgdesc").unwrap_or_default());
+    package["license"] = value(get("license").join(" AND "));
+    if let Some(url) = first("url") {
+        package["url"] = value(url);
+    }
+    for name in ["depends", "makedepends", "provides"] {
+        if !get(name).is_empty() {
+            package[name] = value(array(get(name)));
+        }
+    }
+    if !get("backup").is_empty() {
+        // PKGBUILD backup paths are relative to the root
+        let backup = get("backup")
+            .into_iter()
+            .map(|path| format!("/{}", path.trim_start_matches('/')))
+            .collect();
+        package["backup"] = value(array(backup));
+    }
+    document["package"] = Item::Table(package);
+
+    // sources
+    let checksums = CHECKSUMS
+        .iter()
+        .map(|name| get(name))
+        .find(|checksums| !checksums.is_empty())
+        .unwrap_or_default();
+    let mut sources = ArrayOfTables::new();
+    for (index, source) in get("source").into_iter().enumerate() {
+        if let Some(source) = convert_source(&source, checksums.get(index)) {
+            sources.push(source);
+        }
+    }
+    if !sources.is_empty() {
+        document["source"] = Item::ArrayOfTables(sources);
+    }
+
+    // build
+    let mut build = Table::new();
+    // PKGBUILD functions expect these variables
+    let header = format!(
+        "srcdir=\"$PWD\"\npkgdir=\"$OUT\"\npkgname={}\npkgver={}\n",
+        shell_quote(&names.first().cloned().unwrap_or_default()),
+        shell_quote(&first("pkgver").unwrap_or_default())
+    );
+    let mut script = header.clone();
+    // every PKGBUILD function starts in $srcdir
+    for function in ["build()", "package()"] {
+        if let Some(body) = first(function) {
+            script.push_str(&format!(
+                "\n# {}\ncd \"$srcdir\"\n{}\n",
+                function,
+                dedent(&body)
+            ));
+        }
+    }
+    if let Some(body) = first("prepare()") {
+        build["setup_script"] = script_value(&format!("{}\n{}\n", header, dedent(&body)));
+    }
+    build["script"] = script_value(&script);
+    if let Some(body) = first("check()") {
+        build["check"] = script_value(&format!("{}\n{}\n", header, dedent(&body)));
+    }
+    document["build"] = Item::Table(build);
+
+    document.to_string()
+}
+
+// converts a PKGBUILD source entry, [name::]url[#fragment]
+fn convert_source(source: &str, checksum: Option<&String>) -> Option<Table> {
+    let (name, url) = match source.split_once("::") {
+        Some((name, url)) => (Some(name), url),
+        None => (None, source),
+    };
+
+    if !url.contains("://") {
+        eprintln!(
+            "Warning: local source {} has no PkgFile equivalent, copy it in the build script",
+            url
+        );
+        return None;
+    }
+    if let Some(name) = name {
+        eprintln!("Warning: source {} is not renamed to {}", url, name);
+    }
+
+    let mut table = Table::new();
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+
+    if let Some(url) = url.strip_prefix("git+") {
+        table["source"] = value(url);
+        if !url.ends_with(".git") {
+            eprintln!(
+                "Warning: git source {} has to end in .git to be cloned",
+                url
+            );
+        }
+        match fragment.and_then(|fragment| fragment.split_once('=')) {
+            Some(("commit", commit)) => table["git_commit"] = value(commit),
+            Some(("tag" | "branch", git_ref)) => table["git_ref"] = value(git_ref),
+            Some((kind, _)) => eprintln!("Warning: unsupported git fragment {}", kind),
+            None => {}
+        }
+    } else if let Some(url) = url.strip_prefix("hg+") {
+        table["source"] = value(url);
+        table["vcs"] = value("hg");
+        match fragment.and_then(|fragment| fragment.split_once('=')) {
+            Some(("revision", rev)) => table["hg_rev"] = value(rev),
+            Some(("tag", tag)) => table["hg_tag"] = value(tag),
+            Some(("branch", branch)) => table["hg_branch"] = value(branch),
+            Some((kind, _)) => eprintln!("Warning: unsupported hg fragment {}", kind),
+            None => {}
+        }
+    } else {
+        table["source"] = value(url);
+        if fragment.is_some() {
+            eprintln!("Warning: fragment of {} is ignored", url);
+        }
+    }
+
+    if let Some(checksum) = checksum.filter(|checksum| *checksum != "SKIP") {
+        table["checksum"] = value(checksum.as_str());
+    }
+
+    Some(table)
+}
+
+fn array(values: Vec<String>) -> Array {
+    values.into_iter().collect()
+}
+
+// removes the indentation and the trailing semicolons
+// bash adds to function bodies
+fn dedent(body: &str) -> String {
+    body.lines()
+        .map(|line| line.strip_prefix("    ").unwrap_or(line))
+        .map(strip_separator)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// the ; bash appends after a command, an escaped \; like the end of
+// find -exec and the ;; ending a case branch are part of the command
+fn strip_separator(line: &str) -> &str {
+    let Some(stripped) = line.strip_suffix(';') else {
+        return line;
+    };
+    if stripped.ends_with('\\') || (stripped.ends_with(';') && !stripped.ends_with("\\;")) {
+        return line;
+    }
+    stripped
+}
+
+// single quotes a value for bash
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+// scripts are written as multi-line literal strings so they need no escaping
+fn script_value(script: &str) -> Item {
+    if !script.contains("'''") {
+        if let Ok(literal) = format!("'''\n{}'''", script).parse::<toml_edit::Value>() {
+            return Item::Value(literal);
+        }
+    }
+    value(script)
+}
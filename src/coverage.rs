@@ -0,0 +1,132 @@
+use crate::{build_env, util, PkgBuilderError, PkgFileBuild};
+use std::{collections::BTreeMap, fs, os::unix::fs::PermissionsExt, path::Path, process::Command};
+
+// Collects the coverage data of an instrumented build into <output>/coverage.
+// It is written next to the tarballs and never packaged.
+pub fn collect(
+    build: &PkgFileBuild,
+    vars: &BTreeMap<String, String>,
+    build_dir: &str,
+    out_dir: &str,
+    output_path: &str,
+) {
+    let coverage_dir = format!("{}/coverage", output_path);
+    fs::create_dir_all(&coverage_dir).expect("Unable to create coverage directory");
+    let lcov_file = format!("{}/lcov.info", coverage_dir);
+
+    let collected = if build_env::uses_clang(vars) {
+        collect_llvm(build_dir, out_dir, &lcov_file)
+    } else {
+        require_tool("lcov");
+        run(Command::new("lcov")
+            .arg("--capture")
+            .arg("--directory")
+            .arg(build_dir)
+            .arg("--output-file")
+            .arg(&lcov_file))
+    };
+
+    if !collected {
+        eprintln!("Warning: unable to collect coverage data");
+        return;
+    }
+
+    match build.coverage_format.as_deref().unwrap_or("lcov") {
+        "lcov" => println!("Wrote coverage data to {}", lcov_file),
+        "cobertura" => {
+            require_tool("lcov_cobertura");
+            let cobertura_file = format!("{}/coverage.xml", coverage_dir);
+            if run(Command::new("lcov_cobertura")
+                .arg(&lcov_file)
+                .arg("--output")
+                .arg(&cobertura_file))
+            {
+                println!("Wrote coverage data to {}", cobertura_file);
+            } else {
+                eprintln!("Warning: unable to convert coverage data to cobertura");
+            }
+        }
+        format => eprintln!(
+            "Warning: unknown coverage format {}, wrote lcov to {}",
+            format, lcov_file
+        ),
+    }
+}
+
+// merges the raw profiles and exports them for the installed executables
+fn collect_llvm(build_dir: &str, out_dir: &str, lcov_file: &str) -> bool {
+    require_tool("llvm-profdata");
+    require_tool("llvm-cov");
+
+    let profiles = util::walk_files(Path::new(build_dir))
+        .into_iter()
+        .filter(|file| {
+            file.extension()
+                .is_some_and(|extension| extension == "profraw")
+        })
+        .collect::<Vec<_>>();
+    if profiles.is_empty() {
+        eprintln!("Warning: the build did not write any raw profiles");
+        return false;
+    }
+
+    let profdata = format!("{}/coverage.profdata", build_dir);
+    if !run(Command::new("llvm-profdata")
+        .arg("merge")
+        .arg("-sparse")
+        .args(&profiles)
+        .arg("-o")
+        .arg(&profdata))
+    {
+        return false;
+    }
+
+    let objects = util::walk_files(Path::new(out_dir))
+        .into_iter()
+        .filter(|file| !file.is_symlink())
+        .filter(|file| {
+            fs::metadata(file).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+        })
+        .collect::<Vec<_>>();
+    let Some((first, rest)) = objects.split_first() else {
+        eprintln!("Warning: no executables to map coverage data to");
+        return false;
+    };
+
+    let mut command = Command::new("llvm-cov");
+    command
+        .arg("export")
+        .arg("-format=lcov")
+        .arg(format!("-instr-profile={}", profdata))
+        .arg(first);
+    for object in rest {
+        command.arg("-object").arg(object);
+    }
+
+    let output = command.output().expect("Failed to execute command");
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        return false;
+    }
+    fs::write(lcov_file, output.stdout).expect("Unable to write coverage data");
+    true
+}
+
+fn require_tool(tool: &str) {
+    if !util::command_exists(tool) {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: tool.to_string()
+            }
+        );
+    }
+}
+
+fn run(command: &mut Command) -> bool {
+    let output = command.output().expect("Failed to execute command");
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    output.status.success()
+}
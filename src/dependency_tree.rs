@@ -0,0 +1,131 @@
+use crate::{cli, repo};
+use std::collections::{BTreeMap, BTreeSet};
+
+// pkgbuilder dependency-tree <NAME> <REPO_DIR> [--depth <N>] [--flat] [--reverse]
+//
+// Prints the transitive dependencies of a package from the PACKAGES index
+// of a repository, or with --reverse the packages depending on it.
+// Exits nonzero if a dependency is not in the repository.
+pub fn run(mut args: Vec<String>) {
+    let depth = cli::take_option(&mut args, "--depth")
+        .map(|depth| depth.parse::<usize>().expect("Invalid depth"));
+    let flat = cli::take_flag(&mut args, "--flat");
+    let reverse = cli::take_flag(&mut args, "--reverse");
+    let name = args.first().expect("No package name provided");
+    let repo_dir = args.get(1).expect("No repository path provided");
+
+    let index = repo::read_index(repo_dir);
+
+    // package name -> names of the packages it points to
+    let mut edges: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for package in &index.package {
+        edges.entry(&package.name).or_default();
+        for dependency in package.depends.iter().flatten() {
+            if reverse {
+                edges.entry(dependency).or_default().insert(&package.name);
+            } else {
+                edges.entry(&package.name).or_default().insert(dependency);
+            }
+        }
+    }
+
+    let known = index
+        .package
+        .iter()
+        .map(|package| package.name.as_str())
+        .collect::<BTreeSet<_>>();
+    if !known.contains(name.as_str()) {
+        eprintln!("{} is not in the repository", name);
+        std::process::exit(1);
+    }
+
+    let mut walk = Walk {
+        edges: &edges,
+        known: &known,
+        max_depth: depth,
+        flat,
+        stack: vec![],
+        seen: BTreeSet::new(),
+        missing: BTreeSet::new(),
+        cycles: 0,
+    };
+
+    if !flat {
+        println!("{}", name);
+    }
+    walk.visit(name, 0, "");
+
+    if flat {
+        for package in walk.seen.iter().filter(|package| *package != name) {
+            println!("{}", package);
+        }
+    }
+
+    if walk.cycles > 0 {
+        eprintln!("Warning: found {} dependency cycle(s)", walk.cycles);
+    }
+
+    if !walk.missing.is_empty() {
+        for package in &walk.missing {
+            eprintln!("Error: {} is not in the repository", package);
+        }
+        std::process::exit(1);
+    }
+}
+
+struct Walk<'a> {
+    edges: &'a BTreeMap<&'a str, BTreeSet<&'a str>>,
+    known: &'a BTreeSet<&'a str>,
+    max_depth: Option<usize>,
+    flat: bool,
+    // packages from the root to the current package
+    stack: Vec<&'a str>,
+    seen: BTreeSet<&'a str>,
+    missing: BTreeSet<&'a str>,
+    cycles: usize,
+}
+
+impl<'a> Walk<'a> {
+    fn visit(&mut self, name: &'a str, depth: usize, prefix: &str) {
+        self.seen.insert(name);
+        if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return;
+        }
+
+        self.stack.push(name);
+        let children = self.edges.get(name).cloned().unwrap_or_default();
+        for (index, child) in children.iter().enumerate() {
+            let last = index + 1 == children.len();
+            let cycle = self.stack.contains(child);
+            let missing = !self.known.contains(child);
+
+            if !self.flat {
+                let note = match (cycle, missing) {
+                    (true, _) => " (cycle)",
+                    (_, true) => " (missing)",
+                    _ => "",
+                };
+                let branch = if last { "└── " } else { "├── " };
+                println!("{}{}{}{}", prefix, branch, child, note);
+            }
+
+            if cycle {
+                self.cycles += 1;
+                continue;
+            }
+            if missing {
+                self.missing.insert(child);
+                self.seen.insert(child);
+                continue;
+            }
+            // in a flat listing every package only has to be expanded once
+            if self.flat && self.seen.contains(child) {
+                continue;
+            }
+
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            self.visit(child, depth + 1, &prefix);
+        }
+        self.stack.pop();
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{release, PkgBuilderError, PkgFileSource};
+use std::{
+    fs,
+    path::PathBuf,
+    process::Command,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// Downloads an archive source into file, trying the source url and its mirrors
+// in the order given by mirror_strategy until one succeeds.
+// A download that doesn't match the checksum counts as failed.
+pub fn download_source(source: &PkgFileSource, file: &str) -> bool {
+    let checksum = expected_checksum(source, file).unwrap_or_else(|err| panic!("{}", err));
+    fetch_verified(source, file, checksum.as_deref())
+}
+
+// downloads a source from its url or one of its mirrors without verifying it
+pub fn fetch(source: &PkgFileSource, file: &str) -> bool {
+    fetch_verified(source, file, None)
+}
+
+fn fetch_verified(source: &PkgFileSource, file: &str, checksum: Option<&str>) -> bool {
+    let mut urls = vec![source.source.clone()];
+    urls.extend(source.mirrors.iter().flatten().cloned());
+
+    let urls = match source.mirror_strategy.as_deref().unwrap_or("sequential") {
+        "sequential" => urls,
+        "random" => shuffle(urls),
+        "fastest" => by_latency(urls),
+        strategy => panic!("Unknown mirror strategy: {}", strategy),
+    };
+
+    for url in urls {
+        println!("Downloading {} into {}", url, file);
+
+        // private release assets are downloaded through the API
+        let output = match source.api_download {
+            Some((ref api_url, ref headers)) if url == source.source => {
+                let headers = headers
+                    .iter()
+                    .map(|header| header.0.clone())
+                    .collect::<Vec<_>>();
+                release::curl(&["-fL", api_url, "-o", file], &headers)
+            }
+            _ => Command::new("curl")
+                .arg("-fL")
+                .arg(&url)
+                .arg("-o")
+                .arg(file)
+                .output()
+                .expect("Failed to execute command"),
+        };
+
+        if !output.status.success() {
+            eprintln!(
+                "Download failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            continue;
+        }
+
+        // a mirror serving a bad file is skipped like an unreachable one
+        if let Some(checksum) = checksum {
+            if let Err(err) = verify_checksum(file, checksum) {
+                eprintln!("{}", err);
+                continue;
+            }
+        }
+        return true;
+    }
+
+    false
+}
+
+// the checksum a download has to match, from the checksum field
+// and/or the entry for the source file in hash_file
+fn expected_checksum(
+    source: &PkgFileSource,
+    file: &str,
+) -> Result<Option<String>, PkgBuilderError> {
+    let Some(ref hash_file) = source.hash_file else {
+        return Ok(source.checksum.clone());
+    };
+
+    let filename = source.source.rsplit('/').next().unwrap_or(&source.source);
+    let hash_file_path = format!("{}.hashfile", file);
+
+    println!("Downloading hash file {}", hash_file);
+    let output = Command::new("curl")
+        .arg("-fL")
+        .arg(hash_file)
+        .arg("-o")
+        .arg(&hash_file_path)
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        panic!(
+            "Hash file download failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let contents = fs::read_to_string(&hash_file_path).expect("Unable to read hash file");
+    fs::remove_file(&hash_file_path).expect("Unable to remove hash file");
+
+    let checksum = find_checksum(&contents, filename).ok_or_else(|| {
+        PkgBuilderError::HashFileEntryNotFound {
+            filename: filename.to_string(),
+        }
+    })?;
+
+    if let Some(ref expected) = source.checksum {
+        if !expected.eq_ignore_ascii_case(&checksum) {
+            return Err(PkgBuilderError::ChecksumMismatch {
+                file: hash_file.clone(),
+                expected: expected.clone(),
+                actual: checksum,
+            });
+        }
+    }
+
+    Ok(Some(checksum))
+}
+
+// finds the checksum of a file in the output of a shaXXXsum tool, both
+// GNU (`<hash>  <file>` or `<hash> *<file>`) and BSD (`SHA256 (<file>) = <hash>`)
+// formats are supported
+fn find_checksum(contents: &str, filename: &str) -> Option<String> {
+    for line in contents.lines() {
+        let line = line.trim();
+
+        if let Some((name, hash)) = line.split_once(" = ") {
+            let name = name
+                .split_once(" (")
+                .and_then(|(_, name)| name.strip_suffix(')'));
+            if name
+                .is_some_and(|name| name == filename || name.ends_with(&format!("/{}", filename)))
+            {
+                return Some(hash.trim().to_string());
+            }
+            continue;
+        }
+
+        if let Some((hash, name)) = line.split_once(char::is_whitespace) {
+            let name = name.trim_start().trim_start_matches('*');
+            if name == filename || name.ends_with(&format!("/{}", filename)) {
+                return Some(hash.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+// checks a file against a hex digest, the algorithm is derived from its length
+pub fn verify_checksum(file: &str, checksum: &str) -> Result<(), PkgBuilderError> {
+    let actual = checksum_file(file, checksum_algorithm(checksum));
+
+    if actual.eq_ignore_ascii_case(checksum) {
+        Ok(())
+    } else {
+        Err(PkgBuilderError::ChecksumMismatch {
+            file: file.to_string(),
+            expected: checksum.to_string(),
+            actual,
+        })
+    }
+}
+
+fn checksum_algorithm(checksum: &str) -> &'static str {
+    match checksum.len() {
+        32 => "md5",
+        40 => "sha1",
+        128 => "sha512",
+        _ => "sha256",
+    }
+}
+
+pub fn checksum_file(file: &str, algorithm: &str) -> String {
+    let output = Command::new(format!("{}sum", algorithm))
+        .arg("--")
+        .arg(PathBuf::from(file))
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        panic!(
+            "Failed to compute checksum: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn shuffle(mut urls: Vec<String>) -> Vec<String> {
+    // a simple xorshift seeded from the clock is enough to spread the load
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or(1)
+        | 1;
+
+    for i in (1..urls.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        urls.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+
+    urls
+}
+
+// sends a HEAD request to every url at once and orders them by response time,
+// urls that don't respond are tried last
+fn by_latency(urls: Vec<String>) -> Vec<String> {
+    let handles = urls
+        .into_iter()
+        .map(|url| {
+            thread::spawn(move || {
+                let start = Instant::now();
+                let output = Command::new("curl")
+                    .arg("-fsIL")
+                    .arg("--max-time")
+                    .arg("10")
+                    .arg("-o")
+                    .arg("/dev/null")
+                    .arg(&url)
+                    .output();
+                let latency = match output {
+                    Ok(output) if output.status.success() => Some(start.elapsed()),
+                    _ => None,
+                };
+                (url, latency)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("Mirror check panicked"))
+        .collect::<Vec<_>>();
+
+    for (url, latency) in &results {
+        match latency {
+            Some(latency) => println!("Mirror {} responded in {} ms", url, latency.as_millis()),
+            None => println!("Mirror {} did not respond", url),
+        }
+    }
+
+    results.sort_by_key(|(_, latency)| latency.unwrap_or(Duration::MAX));
+    results.into_iter().map(|(url, _)| url).collect()
+}
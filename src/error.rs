@@ -0,0 +1,71 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PkgBuilderError {
+    #[error("Required tool is not installed: {tool}")]
+    ToolMissing { tool: String },
+    #[error("Version control tool for {vcs} sources is not installed")]
+    VcsToolMissing { vcs: String },
+    #[error("{count} file(s) are claimed by more than one subpackage")]
+    OverlappingSubpackages { count: usize },
+    #[error("Archive {archive} contains an unsafe path: {path}")]
+    UnsafeArchivePath { archive: String, path: String },
+    #[error("Extracting {archive} failed: {message}")]
+    ExtractionFailed { archive: String, message: String },
+    #[error("Source {url} sets conflicting fields: {fields}")]
+    ConflictingSourceFields { url: String, fields: String },
+    #[error("No entry for {filename} in the hash file")]
+    HashFileEntryNotFound { filename: String },
+    #[error("Checksum mismatch for {file}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Unknown sanitizer {sanitizer}, expected address, thread, memory, undefined or none")]
+    UnknownSanitizer { sanitizer: String },
+    #[error("{path} is not a valid PkgFile: {message}")]
+    InvalidPkgFile { path: String, message: String },
+    #[error("Build profile {name} is not defined in the PkgFile")]
+    UnknownProfile { name: String },
+    #[error("Subpackage {name} is {actual_mb} MB, the limit is {limit_mb} MB, adjust its file selectors or install_size_limit_mb")]
+    SubpackageTooLarge {
+        name: String,
+        actual_mb: u64,
+        limit_mb: u64,
+    },
+    #[error("Subpackage {name} is {actual_kb} KB, the minimum is {minimum_kb} KB, adjust its file selectors or install_size_minimum_kb")]
+    SubpackageTooSmall {
+        name: String,
+        actual_kb: u64,
+        minimum_kb: u64,
+    },
+    #[error("Library {name} does not exist")]
+    LibraryNotFound { name: String },
+    #[error("Unknown network policy {policy}, expected full, loopback-only, none or allow-list")]
+    UnknownNetworkPolicy { policy: String },
+    #[error("Network policy {policy} requires root or CAP_NET_ADMIN")]
+    NetworkPolicyRequiresRoot { policy: String },
+    #[error("User {name} does not exist")]
+    UserNotFound { name: String },
+    #[error("Security audit found {} vulnerabilities: {}", vulnerabilities.len(), vulnerabilities.join(", "))]
+    SecurityAuditFailed { vulnerabilities: Vec<String> },
+    #[error("Incompatible ABI changes: {}", changed_symbols.join(", "))]
+    ABIIncompatible { changed_symbols: Vec<String> },
+    #[error("Checking the ABI of {library} failed: {message}")]
+    ABICheckFailed { library: String, message: String },
+    #[error("{name} is listed in the provides of {package} but not in its files")]
+    UnfulfilledProvides { package: String, name: String },
+    #[error("Build output is {actual_mb} MB, the limit is {limit_mb} MB")]
+    OutputTooLarge { actual_mb: u64, limit_mb: u64 },
+    #[error("{file} is {actual_mb} MB, the limit for a single file is {limit_mb} MB")]
+    FileTooLarge {
+        file: String,
+        actual_mb: u64,
+        limit_mb: u64,
+    },
+    #[error("{file} has an RPATH into the build directory: {rpath}")]
+    BadRpath { file: String, rpath: String },
+    #[error("{tarball} has no build log, build it with --save-log")]
+    BuildLogMissing { tarball: String },
+}
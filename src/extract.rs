@@ -0,0 +1,47 @@
+use crate::{archive, cli};
+use std::fs;
+
+// pkgbuilder extract <TARBALL> [--dest <DIR>] [--list] [--run-hooks]
+//
+// Extracts a package tarball with the same safety checks
+// that are applied to downloaded sources.
+pub fn run(mut args: Vec<String>) {
+    let destination = cli::take_option(&mut args, "--dest").unwrap_or_else(|| ".".to_string());
+    let list = cli::take_flag(&mut args, "--list");
+    let run_hooks = cli::take_flag(&mut args, "--run-hooks");
+    let tarball = args.first().expect("No tarball provided");
+
+    if list {
+        let entries = archive::list_tarball(tarball).unwrap_or_else(|err| panic!("{}", err));
+        for entry in entries.iter().map(|entry| display_path(entry)) {
+            if !entry.is_empty() {
+                println!("{}", entry);
+            }
+        }
+        return;
+    }
+
+    fs::create_dir_all(&destination).expect("Unable to create destination directory");
+
+    if run_hooks {
+        archive::run_hook(tarball, "pre_install.sh", &destination);
+    }
+
+    let entries = archive::extract_tarball(tarball, &destination, &[])
+        .unwrap_or_else(|err| panic!("{}", err));
+    println!("Extracted {} into {}:", tarball, destination);
+    for entry in entries.iter().map(|entry| display_path(entry)) {
+        if !entry.is_empty() {
+            println!("{}", entry);
+        }
+    }
+
+    if run_hooks {
+        archive::run_hook(tarball, "post_install.sh", &destination);
+    }
+}
+
+// entries are stored relative to ./ in package tarballs
+fn display_path(entry: &str) -> &str {
+    entry.strip_prefix("./").unwrap_or(entry)
+}
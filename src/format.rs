@@ -0,0 +1,242 @@
+use crate::cli;
+use std::fs;
+use toml_edit::{Array, Document, Item, Table, Value};
+
+// pkgbuilder format <PKGFILE> [--check]
+//
+// Rewrites a PkgFile in the canonical format:
+// [package] fields in a fixed order, sorted dependency lists,
+// double quoted strings, no trailing whitespace
+// and one blank line between sections.
+// With --check the file is left as it is and the exit code is 1
+// if it isn't formatted.
+pub fn run(mut args: Vec<String>) {
+    let check = cli::take_flag(&mut args, "--check");
+    let file_path = args.first().expect("No file path provided");
+
+    let contents = fs::read_to_string(file_path).expect("Unable to read the file");
+    let formatted = format(&contents);
+
+    if formatted == contents {
+        println!("{} is formatted", file_path);
+        return;
+    }
+
+    if check {
+        eprintln!("{} needs formatting", file_path);
+        std::process::exit(1);
+    }
+
+    fs::write(file_path, formatted).expect("Unable to write the PkgFile");
+    println!("Formatted {}", file_path);
+}
+
+// order of the [package] fields, unknown fields keep their order after these
+const PACKAGE_ORDER: [&str; 16] = [
+    "name",
+    "version",
+    "epoch",
+    "pkgrel",
+    "description",
+    "url",
+    "license",
+    "architecture",
+    "depends",
+    "makedepends",
+    "conflicts",
+    "provides",
+    "source_package",
+    "backup",
+    "source_date_epoch",
+    "pkgfile_ignore",
+];
+
+// arrays sorted alphabetically in [package] and [[subpackage]]
+const SORTED_ARRAYS: [&str; 4] = ["depends", "makedepends", "conflicts", "provides"];
+
+fn format(contents: &str) -> String {
+    let mut document = contents
+        .parse::<Document>()
+        .expect("Unable to parse the TOML file");
+
+    if let Some(package) = document
+        .get_mut("package")
+        .and_then(|package| package.as_table_mut())
+    {
+        package.sort_values_by(|key1, _, key2, _| {
+            package_rank(key1.get()).cmp(&package_rank(key2.get()))
+        });
+        sort_arrays(package);
+    }
+
+    if let Some(subpackages) = document
+        .get_mut("subpackage")
+        .and_then(|subpackages| subpackages.as_array_of_tables_mut())
+    {
+        for subpackage in subpackages.iter_mut() {
+            sort_arrays(subpackage);
+        }
+    }
+
+    normalize_table(document.as_table_mut());
+
+    normalize_whitespace(&document.to_string())
+}
+
+fn package_rank(key: &str) -> usize {
+    PACKAGE_ORDER
+        .iter()
+        .position(|name| *name == key)
+        .unwrap_or(PACKAGE_ORDER.len())
+}
+
+fn sort_arrays(table: &mut Table) {
+    for name in SORTED_ARRAYS {
+        let Some(array) = table
+            .get_mut(name)
+            .and_then(|item| item.as_value_mut())
+            .and_then(|value| value.as_array_mut())
+        else {
+            continue;
+        };
+
+        // the whitespace around the values stays in place, e.g. the line breaks
+        // of an array with one value per line
+        let decors = array
+            .iter()
+            .map(|value| value.decor().clone())
+            .collect::<Vec<_>>();
+        let mut values = array.iter().cloned().collect::<Vec<_>>();
+        values.sort_by_key(|value| value.as_str().unwrap_or_default().to_string());
+
+        let mut sorted = Array::new();
+        for (mut value, decor) in values.into_iter().zip(decors) {
+            *value.decor_mut() = decor;
+            sorted.push_formatted(value);
+        }
+        sorted.set_trailing(array.trailing().clone());
+        sorted.set_trailing_comma(array.trailing_comma());
+        *sorted.decor_mut() = array.decor().clone();
+        *array = sorted;
+    }
+}
+
+fn normalize_table(table: &mut Table) {
+    for (_, item) in table.iter_mut() {
+        normalize_item(item);
+    }
+}
+
+fn normalize_item(item: &mut Item) {
+    match item {
+        Item::Value(value) => normalize_value(value),
+        Item::Table(table) => normalize_table(table),
+        Item::ArrayOfTables(tables) => {
+            for table in tables.iter_mut() {
+                normalize_table(table);
+            }
+        }
+        Item::None => {}
+    }
+}
+
+// single line strings are written as basic strings,
+// multi-line strings like scripts keep their quoting
+fn normalize_value(value: &mut Value) {
+    match value {
+        Value::String(string) => {
+            let text = string.value();
+            if text.contains('\n') {
+                return;
+            }
+            let mut quoted = basic_string(text)
+                .parse::<Value>()
+                .expect("Unable to quote string");
+            *quoted.decor_mut() = string.decor().clone();
+            *value = quoted;
+        }
+        Value::Array(array) => {
+            for value in array.iter_mut() {
+                normalize_value(value);
+            }
+        }
+        Value::InlineTable(table) => {
+            for (_, value) in table.iter_mut() {
+                normalize_value(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn basic_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// removes trailing whitespace outside of multi-line strings,
+// collapses blank lines, puts one blank line before every section
+// header and its comments and ends the file with a single newline
+fn normalize_whitespace(contents: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_multiline_string = false;
+
+    for line in contents.lines() {
+        // a line opening or closing a multi-line string has an odd number of delimiters
+        let delimiters = line.matches("'''").count() + line.matches("\"\"\"").count();
+        let toggles = delimiters % 2 == 1;
+
+        // whitespace inside a multi-line string is part of its value
+        if in_multiline_string || toggles {
+            lines.push(line.to_string());
+        } else {
+            let line = line.trim_end();
+            let previous_blank = lines.last().is_none_or(|last| last.is_empty());
+
+            if line.is_empty() {
+                if !previous_blank {
+                    lines.push(String::new());
+                }
+                continue;
+            }
+
+            if line.starts_with('[') && !lines.is_empty() {
+                // the comments directly above a header belong to it
+                let comments = lines
+                    .iter()
+                    .rev()
+                    .take_while(|line| line.starts_with('#'))
+                    .count();
+                let index = lines.len() - comments;
+                if index > 0 && !lines[index - 1].is_empty() {
+                    lines.insert(index, String::new());
+                }
+            }
+
+            lines.push(line.to_string());
+        }
+
+        if toggles {
+            in_multiline_string = !in_multiline_string;
+        }
+    }
+
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    let mut formatted = lines.join("\n");
+    formatted.push('\n');
+    formatted
+}
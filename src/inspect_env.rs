@@ -0,0 +1,66 @@
+use crate::{build_dirs, build_env, cli, read_pkgfile_with_profile};
+
+// variable names containing one of these are not printed in clear text
+const SENSITIVE_NAMES: [&str; 4] = ["TOKEN", "KEY", "SECRET", "PASSWORD"];
+
+// pkgbuilder inspect-env <PKGFILE> [--profile <NAME>] [--shell] [--json]
+//
+// Prints the environment the build script of a PkgFile is started with.
+// Directories and SOURCE_DATE_EPOCH are those of a build started now.
+pub fn run(mut args: Vec<String>) {
+    let shell = cli::take_flag(&mut args, "--shell");
+    let json = cli::take_flag(&mut args, "--json");
+    let profile = cli::take_option(&mut args, "--profile");
+    let file_path = args.first().expect("No PkgFile provided");
+
+    let pkgfile = read_pkgfile_with_profile(file_path, profile.as_deref());
+    let build = pkgfile
+        .build
+        .as_ref()
+        .expect("PkgFile has no [build] section");
+
+    let (build_dir, out_dir, _) = build_dirs(&pkgfile);
+    let source_date_epoch = build_env::source_date_epoch(&pkgfile, &build_dir, false, None);
+
+    let mut vars =
+        build_env::build_environment(&pkgfile, build, &build_dir, &out_dir, source_date_epoch);
+    // python_path links and compiler wrappers are only needed by a real build
+    let _ = std::fs::remove_dir_all(format!("{}.python", build_dir));
+    let _ = std::fs::remove_dir_all(format!("{}.iwyu", build_dir));
+    let _ = std::fs::remove_dir_all(format!("{}.sccache", build_dir));
+    for (name, value) in vars.iter_mut() {
+        if SENSITIVE_NAMES
+            .iter()
+            .any(|sensitive| name.contains(sensitive))
+        {
+            *value = "***".to_string();
+        }
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&vars).expect("Unable to serialize environment")
+        );
+    } else if shell {
+        for (name, value) in &vars {
+            println!("export {}=\"{}\"", name, shell_escape(value));
+        }
+    } else {
+        for (name, value) in &vars {
+            println!("{}={}", name, value);
+        }
+    }
+}
+
+// escapes a value for use inside double quotes
+fn shell_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
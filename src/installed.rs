@@ -0,0 +1,14 @@
+use std::path::PathBuf;
+
+// The installed-package database.
+// Every installed package has a directory below
+// <sysroot>/var/lib/pkgbuilder/installed/<name>/
+// containing its package.toml and FILES manifest.
+
+pub fn package_dir(sysroot: &str, name: &str) -> PathBuf {
+    database_dir(sysroot).join(name)
+}
+
+pub fn database_dir(sysroot: &str) -> PathBuf {
+    PathBuf::from(sysroot).join("var/lib/pkgbuilder/installed")
+}
@@ -0,0 +1,119 @@
+use crate::{util, PkgBuilderError, PkgFileBuild};
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+// Collects the output of include-what-you-use from an iwyu build.
+// The compiler wrappers set up by build_env append it to iwyu.log in
+// <build_dir>.iwyu, the suggestions are written next to the tarballs.
+pub fn collect(build: &PkgFileBuild, build_dir: &str, output_path: &str) {
+    let log = fs::read_to_string(format!("{}.iwyu/iwyu.log", build_dir)).unwrap_or_default();
+
+    let suggestions = suggestions(&log);
+    if suggestions.is_empty() {
+        println!("include-what-you-use has no suggestions");
+    } else {
+        fs::create_dir_all(output_path).expect("Unable to create output directory");
+        let path = format!("{}/iwyu_suggestions.txt", output_path);
+        fs::write(&path, suggestions.join("\n")).expect("Unable to write iwyu suggestions");
+        println!(
+            "Wrote {} include-what-you-use suggestion(s) to {}",
+            suggestions.len(),
+            path
+        );
+    }
+
+    if build.iwyu_fix.unwrap_or(false) {
+        fix(build_dir);
+    }
+}
+
+// the "should add" and "should remove" blocks of the output,
+// the full include lists repeat what is already there
+fn suggestions(log: &str) -> Vec<String> {
+    let mut suggestions = Vec::new();
+    let mut current: Option<String> = None;
+
+    for line in log.lines().chain([""]) {
+        match current {
+            Some(ref mut block) if !line.trim().is_empty() && line != "---" => {
+                block.push_str(line);
+                block.push('\n');
+            }
+            // a block ends at the next blank line,
+            // headers without lines below them are not suggestions
+            Some(block) => {
+                if block.lines().count() > 1 {
+                    suggestions.push(block);
+                }
+                current = None;
+            }
+            None if line.ends_with("should add these lines:")
+                || line.ends_with("should remove these lines:") =>
+            {
+                current = Some(format!("{}\n", line));
+            }
+            None => {}
+        }
+    }
+
+    suggestions
+}
+
+// applies the suggestions to the sources in the build directory with
+// iwyu_tool.py, which needs the compile_commands.json of the build
+fn fix(build_dir: &str) {
+    for tool in ["iwyu_tool.py", "fix_includes.py"] {
+        if !util::command_exists(tool) {
+            panic!(
+                "{}",
+                PkgBuilderError::ToolMissing {
+                    tool: tool.to_string()
+                }
+            );
+        }
+    }
+
+    let database = util::walk_files(Path::new(build_dir))
+        .into_iter()
+        .find(|file| {
+            file.file_name()
+                .is_some_and(|name| name == "compile_commands.json")
+        });
+    let Some(database_dir) = database.as_deref().and_then(Path::parent) else {
+        eprintln!("Warning: no compile_commands.json in the build directory, not applying iwyu suggestions");
+        return;
+    };
+
+    let output = Command::new("iwyu_tool.py")
+        .arg("-p")
+        .arg(database_dir)
+        .current_dir(database_dir)
+        .output()
+        .expect("Failed to execute command");
+
+    let mut child = Command::new("fix_includes.py")
+        .current_dir(database_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+    child
+        .stdin
+        .take()
+        .expect("Failed to open stdin")
+        .write_all(&output.stdout)
+        .expect("Failed to write to stdin");
+
+    let output = child.wait_with_output().expect("Failed to wait on child");
+    // fix_includes.py exits with the number of changed files
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    println!(
+        "Applied include-what-you-use suggestions to the sources in {}",
+        database_dir.display()
+    );
+}
@@ -0,0 +1,218 @@
+use crate::{cli, installed, repo, PkgFile};
+use serde::Serialize;
+use std::{cmp::Ordering, fs};
+
+// pkgbuilder list-provided <REPO_DIR> --provides <CAPABILITY> [--json]
+// pkgbuilder list-provided --provides <CAPABILITY> --installed [--sysroot <PATH>] [--json]
+//
+// Lists the packages providing a capability, e.g. libssl.so.3, from the
+// PACKAGES index of a repository or from the installed-package database.
+// The capability can be version qualified, e.g. libssl.so.3>=3.0.
+// A provides entry like libssl.so.3=3.0.2 carries its own version,
+// otherwise the version of the package is used.
+// Every package provides its own name.
+// Exits with 1 if no package provides the capability.
+pub fn run(mut args: Vec<String>) {
+    let capability = cli::take_option(&mut args, "--provides").expect("No capability provided");
+    let installed = cli::take_flag(&mut args, "--installed");
+    let sysroot = cli::take_option(&mut args, "--sysroot").unwrap_or_else(|| "/".to_string());
+    let json = cli::take_flag(&mut args, "--json");
+
+    let query = Query::parse(&capability);
+
+    let packages = if installed {
+        installed_packages(&sysroot)
+    } else {
+        repo_packages(args.first().expect("No repository path provided"))
+    };
+
+    let providers = packages
+        .iter()
+        .filter_map(|package| package.provider(&query))
+        .collect::<Vec<_>>();
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&providers).expect("Unable to serialize providers")
+        );
+    } else {
+        for provider in &providers {
+            println!(
+                "{} {} (provides {} {})",
+                provider.package, provider.package_version, provider.provides, provider.version
+            );
+        }
+    }
+
+    if providers.is_empty() {
+        eprintln!("No package provides {}", capability);
+        std::process::exit(1);
+    }
+}
+
+// a capability with an optional version constraint
+struct Query {
+    name: String,
+    constraint: Option<(Ordering, bool, String)>,
+}
+
+impl Query {
+    // name, name=version, name>=version, name<version, ...
+    fn parse(query: &str) -> Query {
+        let Some(index) = query.find(['<', '>', '=']) else {
+            return Query {
+                name: query.to_string(),
+                constraint: None,
+            };
+        };
+
+        let (name, constraint) = query.split_at(index);
+        // the expected ordering of the provided version against the queried one
+        // and whether equal versions satisfy it too
+        let (ordering, or_equal, version) = if let Some(version) = constraint.strip_prefix(">=") {
+            (Ordering::Greater, true, version)
+        } else if let Some(version) = constraint.strip_prefix("<=") {
+            (Ordering::Less, true, version)
+        } else if let Some(version) = constraint.strip_prefix('>') {
+            (Ordering::Greater, false, version)
+        } else if let Some(version) = constraint.strip_prefix('<') {
+            (Ordering::Less, false, version)
+        } else {
+            let version = constraint.trim_start_matches('=');
+            (Ordering::Equal, true, version)
+        };
+
+        if name.is_empty() || version.is_empty() {
+            panic!("Invalid capability {}", query);
+        }
+
+        Query {
+            name: name.to_string(),
+            constraint: Some((ordering, or_equal, version.to_string())),
+        }
+    }
+
+    fn matches(&self, name: &str, version: &str) -> bool {
+        if name != self.name {
+            return false;
+        }
+
+        let Some((ref ordering, or_equal, ref expected)) = self.constraint else {
+            return true;
+        };
+        let actual = repo::compare_versions(version, expected);
+        actual == *ordering || (or_equal && actual == Ordering::Equal)
+    }
+}
+
+struct Package {
+    name: String,
+    version: String,
+    provides: Vec<String>,
+    // tarball or database directory the package was read from
+    location: String,
+}
+
+#[derive(Serialize)]
+struct Provider {
+    package: String,
+    package_version: String,
+    provides: String,
+    version: String,
+    location: String,
+}
+
+impl Package {
+    fn from_pkgfile(name: String, pkgfile: Option<PkgFile>, location: String) -> Package {
+        // subpackages share the package.toml of their main package,
+        // their provides are in their [[subpackage]] table
+        let (version, provides) = match pkgfile {
+            Some(pkgfile) if pkgfile.package.name == name => (
+                pkgfile.package.version,
+                pkgfile.package.provides.unwrap_or_default(),
+            ),
+            Some(pkgfile) => {
+                let provides = pkgfile
+                    .subpackage
+                    .into_iter()
+                    .flatten()
+                    .find(|subpackage| subpackage.name == name)
+                    .and_then(|subpackage| subpackage.provides)
+                    .unwrap_or_default();
+                (pkgfile.package.version, provides)
+            }
+            None => (String::new(), Vec::new()),
+        };
+
+        Package {
+            name,
+            version,
+            provides,
+            location,
+        }
+    }
+
+    // the first provides entry satisfying the query
+    fn provider(&self, query: &Query) -> Option<Provider> {
+        std::iter::once(&self.name)
+            .chain(&self.provides)
+            .find_map(|entry| {
+                let (name, version) = entry
+                    .split_once('=')
+                    .unwrap_or((entry, self.version.as_str()));
+                query.matches(name, version).then(|| Provider {
+                    package: self.name.clone(),
+                    package_version: self.version.clone(),
+                    provides: name.to_string(),
+                    version: version.to_string(),
+                    location: self.location.clone(),
+                })
+            })
+    }
+}
+
+fn repo_packages(repo_dir: &str) -> Vec<Package> {
+    repo::read_index(repo_dir)
+        .package
+        .into_iter()
+        .map(|package| {
+            let tarball = format!("{}/{}", repo_dir, package.filename);
+            let pkgfile = repo::read_package_metadata(&tarball);
+            if pkgfile.is_none() {
+                eprintln!(
+                    "Warning: {} has no readable package.toml, only its name is checked",
+                    package.filename
+                );
+            }
+
+            let mut found = Package::from_pkgfile(package.name, pkgfile, tarball);
+            found.version = package.version;
+            found
+        })
+        .collect()
+}
+
+fn installed_packages(sysroot: &str) -> Vec<Package> {
+    let mut names = fs::read_dir(installed::database_dir(sysroot))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let dir = installed::package_dir(sysroot, &name);
+            let pkgfile = fs::read_to_string(dir.join("package.toml"))
+                .ok()
+                .and_then(|metadata| toml::from_str::<PkgFile>(&metadata).ok());
+            Package::from_pkgfile(name, pkgfile, dir.to_string_lossy().into_owned())
+        })
+        .collect()
+}
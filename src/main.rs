@@ -1,33 +1,108 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     env, fs,
     fs::File,
-    io::{self, Read},
+    io::{self, Read, Write},
+    os::unix::fs::PermissionsExt,
     process::{Command, Stdio},
 };
-use toml;
 
+mod abi;
+mod archive;
+mod audit;
+mod build_batch;
+mod build_env;
+mod check_updates;
+mod cli;
+mod config;
+mod convert_pkgbuild;
+mod coverage;
+mod dependency_tree;
+mod download;
+mod error;
+mod extract;
+mod format;
+mod inspect_env;
+mod installed;
+mod iwyu;
+mod list_provided;
+mod manifest;
+mod metadata;
+mod network;
+mod pack;
+mod postprocess;
+mod profile;
+mod release;
+mod repo;
+mod schema;
+mod show_build_log;
+mod sign_key;
+mod timing;
+mod update_checksums;
+mod upgrade;
+mod util;
+mod verify;
+mod verify_installed;
+mod verify_repo;
+mod worktree;
+
+use error::PkgBuilderError;
+
+// schema.rs describes these structs as a JSON Schema,
+// new fields have to be added there as well
 #[derive(Debug, Deserialize)]
 struct PkgFile {
     package: PkgFilePackage,
     subpackage: Option<Vec<PkgFileSubPackage>>,
     source: Option<Vec<PkgFileSource>>,
     build: Option<PkgFileBuild>,
+    output: Option<PkgFileOutput>,
 }
 
 #[derive(Debug, Deserialize)]
 struct PkgFilePackage {
     name: String,
     version: String,
+    // release number of the package, bumped when the PkgFile changes
+    // without a new upstream version, defaults to 1
+    pkgrel: Option<u32>,
+    // ignore file relative to the PkgFile, default is .pkgbuilderignore
+    pkgfile_ignore: Option<String>,
+    // unix timestamp used as SOURCE_DATE_EPOCH, default is the build time
+    source_date_epoch: Option<u64>,
+    // configuration files that are not overwritten on upgrade
+    // when they were modified after installation
+    backup: Option<Vec<String>>,
+    // packages required at runtime and at build time
+    depends: Option<Vec<String>>,
+    makedepends: Option<Vec<String>>,
+    // capabilities such as shared library SONAMEs this package provides
+    provides: Option<Vec<String>>,
+    // name of the source package this package is built from,
+    // e.g. gcc for gcc-libs, defaults to name
+    source_package: Option<String>,
+    #[allow(dead_code)]
+    url: Option<String>,
+    #[allow(dead_code)]
     description: String,
+    #[allow(dead_code)]
     license: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct PkgFileSubPackage {
     name: String,
+    #[allow(dead_code)]
     description: String,
     files: Vec<String>,
+    // names of packages this subpackage depends on
+    depends: Option<Vec<String>>,
+    // bounds for the size of the selected files
+    install_size_limit_mb: Option<u64>,
+    install_size_minimum_kb: Option<u64>,
+    // capabilities such as shared library SONAMEs this subpackage provides
+    provides: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,74 +112,774 @@ struct PkgFileSource {
     git_commit: Option<String>,
     // default is root of the build directory
     destination: Option<String>,
+    // password for encrypted .zip archives,
+    // use $ENV{VAR} to read it from an environment variable
+    archive_password: Option<Secret>,
+    // canonical upstream url when source is a mirror,
+    // only recorded as metadata, the clone still uses source
+    git_mirror_of: Option<String>,
+    // version control system used to fetch the source,
+    // detected from the url when not set
+    vcs: Option<String>,
+    // extra flags for rsync sources
+    rsync_options: Option<Vec<String>>,
+    // extract archives into <destination>/<archive name without extension>,
+    // default is false
+    extract_to_subdirectory: Option<bool>,
+    // alternative urls of an archive source
+    mirrors: Option<Vec<String>>,
+    // order the source url and mirrors are tried in:
+    // sequential (default), random or fastest
+    mirror_strategy: Option<String>,
+    // number of commits to clone, default is 1
+    git_depth: Option<u32>,
+    // clone the history since a date instead, e.g. "1 year ago" or "2024-01-01"
+    git_shallow_since: Option<String>,
+    // revision to check out for vcs = "hg" sources,
+    // hg_rev takes precedence over hg_tag, which takes precedence over hg_branch
+    hg_rev: Option<String>,
+    hg_tag: Option<String>,
+    hg_branch: Option<String>,
+    // hex digest the downloaded archive has to match,
+    // md5, sha1, sha256 or sha512 depending on its length
+    checksum: Option<String>,
+    // url of a shaXXXsums file to take the checksum from
+    hash_file: Option<String>,
+    // local git bundle to clone from instead of the source url,
+    // the source is still cloned if the bundle doesn't exist
+    git_bundle: Option<String>,
+    // check out a worktree of a bare repository in the cache directory
+    // shared between builds instead of cloning, default is false
+    git_worktree: Option<bool>,
+    // download the asset of a GitHub or GitLab release matching asset_pattern,
+    // source is replaced with the download url of the asset
+    github_release: Option<PkgFileGitHubRelease>,
+    gitlab_release: Option<PkgFileGitLabRelease>,
+    // api url and headers for release assets of private repositories
+    #[serde(skip)]
+    api_download: Option<(String, Vec<Secret>)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PkgFileGitHubRelease {
+    owner: String,
+    repo: String,
+    tag: String,
+    // glob pattern matched against the asset names
+    asset_pattern: String,
+    // GitHub Enterprise host, default is github.com
+    host: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PkgFileGitLabRelease {
+    // path of the project including its groups, e.g. group/subgroup/project
+    project: String,
+    tag: String,
+    // glob pattern matched against the asset names
+    asset_pattern: String,
+    // default is gitlab.com
+    host: Option<String>,
+}
+
+const ARCHIVE_EXTENSIONS: [&str; 6] =
+    [".tar.gz", ".tgz", ".tar.bz2", ".tar.xz", ".tar.zst", ".zip"];
+
+impl PkgFileSource {
+    fn archive_extension(&self) -> Option<&'static str> {
+        ARCHIVE_EXTENSIONS
+            .into_iter()
+            .find(|extension| self.source.ends_with(extension))
+    }
+
+    // directory the source is cloned or extracted into
+    fn destination_dir(&self, build_dir: &str) -> String {
+        let destination = match self.destination {
+            Some(ref destination) => format!("{}{}", build_dir, destination),
+            None => build_dir.to_string(),
+        };
+
+        match self.archive_extension() {
+            Some(extension) if self.extract_to_subdirectory.unwrap_or(false) => {
+                let file_name = self.source.rsplit('/').next().unwrap_or(&self.source);
+                format!(
+                    "{}/{}",
+                    destination.trim_end_matches('/'),
+                    file_name.strip_suffix(extension).unwrap_or(file_name)
+                )
+            }
+            _ => destination,
+        }
+    }
+
+    // the upstream url of the source, used for metadata instead of the mirror url
+    fn canonical_url(&self) -> &str {
+        self.git_mirror_of.as_deref().unwrap_or(&self.source)
+    }
+}
+
+// A string that is never printed, not even in debug output
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct Secret(String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "***")
+    }
+}
+
+impl Secret {
+    // resolves $ENV{VAR} references, plain values are returned as is
+    fn resolve(&self) -> String {
+        match self
+            .0
+            .strip_prefix("$ENV{")
+            .and_then(|var| var.strip_suffix('}'))
+        {
+            Some(var) => {
+                env::var(var).unwrap_or_else(|_| panic!("Environment variable {} is not set", var))
+            }
+            None => self.0.clone(),
+        }
+    }
+
+    fn is_plaintext(&self) -> bool {
+        !(self.0.starts_with("$ENV{") && self.0.ends_with('}'))
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct PkgFileBuild {
     script: String,
+    // run the build script under fakeroot so ownership changes are recorded
+    // and applied to the tarballs, default is false
+    fakeroot: Option<bool>,
+    // compile with distcc, hosts default to the DISTCC_HOSTS environment variable
+    distcc: Option<bool>,
+    distcc_hosts: Option<Vec<String>>,
+    distcc_jobs: Option<u32>,
+    // remove libtool .la archives from the output, default is true
+    strip_la_files: Option<bool>,
+    // glob patterns of .la files that are kept anyway
+    keep_la_files: Option<Vec<String>>,
+    // package documentation from usr/share/doc, info and gtk-doc, default is true
+    install_docs: Option<bool>,
+    // locales to keep, e.g. ["en_US", "fr_FR"], all locales are kept when not set
+    locale_filter: Option<Vec<String>>,
+    // prefix the package is installed to, default is /usr
+    install_prefix: Option<String>,
+    // passed to the build script as -D<KEY>=<VALUE> arguments
+    // in CMAKE_EXTRA_ARGS and MESON_EXTRA_ARGS
+    cmake_flags: Option<BTreeMap<String, String>>,
+    meson_flags: Option<BTreeMap<String, String>>,
+    // scripts run before and after upgrading an installed version of the package
+    pre_upgrade: Option<String>,
+    post_upgrade: Option<String>,
+    // move every locale in usr/share/locale into its own
+    // <name>-locale-<lang> subpackage, default is false
+    split_locale_packages: Option<bool>,
+    // fail the build when the output or a single file in it is larger than this
+    max_output_size_mb: Option<u64>,
+    max_single_file_size_mb: Option<u64>,
+    // cache compilation with sccache, optionally in a cloud storage bucket
+    sccache: Option<bool>,
+    sccache_bucket: Option<String>,
+    sccache_region: Option<String>,
+    // environment for python packages, also sets PIP_PREFIX and PYTHONHASHSEED
+    python_flags: Option<PkgFilePythonFlags>,
+    // write usr/lib/pkgconfig/<name>.pc unless the build installed one
+    generate_pkg_config: Option<PkgConfigSpec>,
+    // address, thread, memory, undefined or none
+    sanitizer: Option<String>,
+    // instrument the build and write coverage/ into the output directory,
+    // the format is lcov (default) or cobertura
+    coverage: Option<bool>,
+    coverage_format: Option<String>,
+    // runs before the build script, e.g. autoreconf -fi,
+    // in setup_workdir relative to the build directory
+    setup_script: Option<String>,
+    // runs after the build script, e.g. the test suite
+    check: Option<String>,
+    setup_workdir: Option<String>,
+    // allow bundled copies of libraries, by default the system libraries are preferred,
+    // exceptions are recorded as vendored_libs in package.toml
+    use_vendored_libs: Option<bool>,
+    vendored_exceptions: Option<Vec<String>>,
+    // add the SONAMEs of the built shared libraries to package.provides
+    auto_provides: Option<bool>,
+    // user to run the scripts as when pkgbuilder runs as root
+    force_user: Option<String>,
+    // trust every repository in git, for sources cloned by another user
+    git_config_safe_directory: Option<bool>,
+    // names or glob patterns, e.g. "AWS_*", of variables removed from the build environment
+    host_env_deny_list: Option<Vec<String>>,
+    // link without and strip .note.gnu.build-id for reproducible binaries, default is false
+    no_build_id: Option<bool>,
+    // compile *.py files into __pycache__, also optimized, default is false
+    python_bytecode_compile: Option<bool>,
+    // subpackages generated from glob patterns, split off before the listed subpackages
+    split_files_by_pattern: Option<Vec<PkgFileSplitRule>>,
+    // audit the dependencies of rust, node and python sources before building
+    audit_deps: Option<bool>,
+    // previous tarball, relative to the output path, to compare the shared libraries with
+    abi_check: Option<String>,
+    // symbols whose incompatible ABI changes are intentional
+    abi_check_whitelist: Option<Vec<String>>,
+    // replace PATH with the system directories and the directories of the makedepends
+    hermetic: Option<bool>,
+    // directories added to the PATH of hermetic builds
+    hermetic_extra_paths: Option<Vec<String>>,
+    // write the wall clock and CPU time of each build phase
+    // to <output_path>/build_timing.json, default is false
+    perf_events: Option<bool>,
+    // fail when a file or shared library listed in the provides of a package
+    // or subpackage is not in its files, default is false
+    check_provides: Option<bool>,
+    // libraries preloaded into the scripts, absolute paths or names
+    // looked up in the ldconfig cache, e.g. libfaketime.so.1
+    ld_preload: Option<Vec<String>>,
+    // network access of the scripts: full (default), loopback-only, none
+    // or allow-list, which requires root or CAP_NET_ADMIN
+    network_policy: Option<String>,
+    // networks the scripts may connect to with allow-list, e.g. 10.0.0.0/8
+    network_allow_cidrs: Option<Vec<String>>,
+    // warn about temporary and empty files in the output, default is false
+    tempfile_cleanup: Option<bool>,
+    // remove the temporary files instead, empty files are only reported,
+    // only used with tempfile_cleanup
+    tempfile_autoremove: Option<bool>,
+    // fail when an ELF file has an RPATH or RUNPATH pointing into
+    // the build directory, default is false
+    assert_no_rpath: Option<bool>,
+    // RPATH entries accepted by assert_no_rpath, glob patterns
+    rpath_whitelist: Option<Vec<String>>,
+    // run ldconfig in post_install.sh and post_upgrade.sh when the package
+    // installs shared libraries to /lib or /usr/lib, enabled when a versioned
+    // library (*.so.*) is found unless set to false
+    ldconfig_update: Option<bool>,
+    // interpreter used as python3 and python by the build, e.g. /usr/bin/python3.11
+    python_path: Option<String>,
+    // false unsets PYTHONPATH and PYTHONSTARTUP of the host, default is true
+    use_system_python: Option<bool>,
+    // compile C and C++ sources with include-what-you-use as well and write
+    // its suggestions to <output_path>/iwyu_suggestions.txt, for development only
+    iwyu: Option<bool>,
+    // apply the suggestions to the sources with iwyu_tool.py and fix_includes.py
+    iwyu_fix: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PkgFileSplitRule {
+    // the subpackage is named <package>-<name_suffix>
+    name_suffix: String,
+    // {name} is replaced with the package name
+    description_template: String,
+    files: Vec<String>,
+    // default is the main package
+    depends: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PkgConfigSpec {
+    name: String,
+    description: String,
+    // default is the package version
+    version: Option<String>,
+    // default is the install prefix
+    prefix: Option<String>,
+    libs: Option<String>,
+    cflags: Option<String>,
+    requires: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PkgFilePythonFlags {
+    // appended to PYTHONPATH
+    site_packages_dir: Option<String>,
+    // sets PYTHONDONTWRITEBYTECODE when false, default is true
+    write_bytecode: Option<bool>,
+    // appended to PIP_ARGS
+    pip_args: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PkgFileOutput {
+    // owner and group of all files in the tarballs, default is root
+    tarball_owner: Option<String>,
+    tarball_group: Option<String>,
+    // strip setuid and setgid bits from all files, default is false
+    tarball_normalize_permissions: Option<bool>,
+    // files that keep their setuid and setgid bits, e.g. /usr/bin/sudo
+    tarball_keep_setuid: Option<Vec<String>>,
+    // put tarballs and the PACKAGES index into <output_path>/<arch>/,
+    // default is false
+    split_arch_dir: Option<bool>,
+    // embed package.toml in the tarballs, default is true
+    include_pkgfile: Option<bool>,
 }
 
 fn main() {
-    let file_path = env::args().nth(1).unwrap_or_else(|| {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("build-batch") => {
+            args.remove(0);
+            build_batch::run(args);
+        }
+        Some("inspect-env") => {
+            args.remove(0);
+            inspect_env::run(args);
+        }
+        Some("list-provided") => {
+            args.remove(0);
+            list_provided::run(args);
+        }
+        Some("dependency-tree") => {
+            args.remove(0);
+            dependency_tree::run(args);
+        }
+        Some("generate-schema") => {
+            args.remove(0);
+            schema::run_generate(args);
+        }
+        Some("validate-schema") => {
+            args.remove(0);
+            schema::run_validate(args);
+        }
+        Some("convert-pkgbuild") => {
+            args.remove(0);
+            convert_pkgbuild::run(args);
+        }
+        Some("update-checksums") => {
+            args.remove(0);
+            update_checksums::run(args);
+        }
+        Some("check-updates") => {
+            args.remove(0);
+            check_updates::run(args);
+        }
+        Some("format") => {
+            args.remove(0);
+            format::run(args);
+        }
+        Some("pack") => {
+            args.remove(0);
+            pack::run(args);
+        }
+        Some("extract") => {
+            args.remove(0);
+            extract::run(args);
+        }
+        Some("sign-key") => {
+            args.remove(0);
+            sign_key::run(args);
+        }
+        Some("upgrade") => {
+            args.remove(0);
+            upgrade::run(args);
+        }
+        Some("show-build-log") => {
+            args.remove(0);
+            show_build_log::run(args);
+        }
+        Some("verify-installed") => {
+            args.remove(0);
+            verify_installed::run(args);
+        }
+        Some("verify-repo") => {
+            args.remove(0);
+            verify_repo::run(args);
+        }
+        Some("verify") => {
+            args.remove(0);
+            verify::run(args);
+        }
+        _ => build(args),
+    }
+}
+
+fn read_pkgfile(file_path: &str) -> PkgFile {
+    read_pkgfile_with_profile(file_path, None)
+}
+
+// reads a PkgFile with a build profile applied,
+// None selects the default profile
+fn read_pkgfile_with_profile(file_path: &str, profile: Option<&str>) -> PkgFile {
+    try_read_pkgfile(file_path, profile).unwrap_or_else(|err| panic!("{}", err))
+}
+
+// like read_pkgfile_with_profile, but returns an error for unreadable
+// or invalid PkgFiles
+fn try_read_pkgfile(file_path: &str, profile: Option<&str>) -> Result<PkgFile, PkgBuilderError> {
+    let invalid = |message: String| PkgBuilderError::InvalidPkgFile {
+        path: file_path.to_string(),
+        message,
+    };
+
+    let contents = fs::read_to_string(file_path).map_err(|err| invalid(err.to_string()))?;
+    let mut pkgfile: toml::Table =
+        toml::from_str(&contents).map_err(|err| invalid(err.to_string()))?;
+    profile::apply(&mut pkgfile, profile)?;
+
+    pkgfile
+        .try_into()
+        .map_err(|err: toml::de::Error| invalid(err.to_string()))
+}
+
+fn build(mut args: Vec<String>) {
+    let mut timer = timing::BuildTimer::new();
+    timer.phase("pre_source");
+
+    let allow_overlap = cli::take_flag(&mut args, "--allow-overlap");
+    let reproducible = cli::take_flag(&mut args, "--reproducible");
+    let ignore_file = cli::take_option(&mut args, "--pkgbuilderignore");
+    let force_timestamp = cli::take_option(&mut args, "--force-timestamp")
+        .map(|epoch| epoch.parse::<u64>().expect("Invalid timestamp"));
+    let profile = cli::take_option(&mut args, "--profile");
+    let global_git_safe_dir = cli::take_flag(&mut args, "--global-git-safe-dir");
+    // embed the output of the scripts as build.log.zst in the main package
+    let save_log = cli::take_flag(&mut args, "--save-log");
+    // leave PACKAGES to the caller, build-batch writes it once at the end
+    let no_index = cli::take_flag(&mut args, "--no-index");
+    // comma separated advisory ids accepted by audit_deps
+    let audit_ignore_ids = cli::take_option(&mut args, "--audit-ignore-ids")
+        .map(|ids| {
+            ids.split(',')
+                .map(|id| id.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let file_path = args.first().cloned().unwrap_or_else(|| {
         env::var("PKGBUILDER_PKGFILE_PATH").unwrap_or_else(|_| panic!("No file path provided"))
     });
 
-    let output_path = env::args().nth(2).unwrap_or_else(|| {
+    let output_path = args.get(1).cloned().unwrap_or_else(|| {
         env::var("PKGBUILDER_OUTPUT_PATH").unwrap_or_else(|_| panic!("No output path provided"))
     });
 
-    let mut file = File::open(&file_path).expect("Unable to open the file");
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .expect("Unable to read the file");
-
-    let package_file: PkgFile = toml::from_str(&contents).expect("Unable to parse the TOML file");
+    let mut package_file = read_pkgfile_with_profile(&file_path, profile.as_deref());
+    for source in package_file.source.iter_mut().flatten() {
+        release::resolve(source);
+    }
+    // recorded in the FILES manifests: the assets of release sources
+    // and the upstream url of mirrored git sources
+    let source_urls = package_file
+        .source
+        .iter()
+        .flatten()
+        .filter(|source| {
+            source.github_release.is_some()
+                || source.gitlab_release.is_some()
+                || source.git_mirror_of.is_some()
+        })
+        .map(|source| source.canonical_url().to_string())
+        .collect::<Vec<_>>();
     println!("{:#?}", package_file);
 
-    let (build_dir, out_dir, package_dir) = setup_build_environment(&package_file);
+    // recorded before the name can change for sanitizer builds
+    package_file
+        .package
+        .source_package
+        .get_or_insert_with(|| package_file.package.name.clone());
+
+    // sanitizer builds are published under a separate name
+    if let Some(sanitizer) = package_file.build.as_ref().and_then(build_env::sanitizer) {
+        eprintln!("Warning: sanitizer builds are for debugging only and should not be distributed");
+        package_file.package.name =
+            format!("{}-sanitizer-{}", package_file.package.name, sanitizer);
+    }
+
+    let perf_events = package_file
+        .build
+        .as_ref()
+        .and_then(|build| build.perf_events)
+        .unwrap_or(false);
+
+    timer.phase("source_fetch");
+    let (build_dir, out_dir, package_dir) = setup_build_environment(&package_file, reproducible);
+    timer.phase("prepare");
+    // removed when the build finishes or panics
+    let worktrees = worktree::Worktrees::new(&package_file, &build_dir);
+
+    let source_date_epoch =
+        build_env::source_date_epoch(&package_file, &build_dir, reproducible, force_timestamp);
 
     // execute build script in build directory
+    // fakeroot keeps the faked ownership in this file between invocations
+    let fakeroot_state = format!("{}.fakeroot", out_dir);
+    let mut use_fakeroot = false;
+    // global git config of the build when safe.directory is relaxed
+    let git_config = format!("{}.gitconfig", build_dir);
+    // output of the scripts with --save-log
+    let build_log = format!("{}.log", build_dir);
+    // python3 and python linked to python_path
+    let python_dir = format!("{}.python", build_dir);
+    // compiler wrappers and the output of include-what-you-use
+    let iwyu_dir = format!("{}.iwyu", build_dir);
+    // compiler wrappers calling sccache
+    let sccache_dir = format!("{}.sccache", build_dir);
+
+    let build_user = package_file.build.as_ref().and_then(|build| {
+        let user = build.force_user.as_deref()?;
+        let mut paths = vec![build_dir.as_str(), out_dir.as_str(), package_dir.as_str()];
+        if build.fakeroot.unwrap_or(false) {
+            paths.push(&fakeroot_state);
+        }
+        // the compiler wrappers append to iwyu.log in it
+        if build.iwyu.unwrap_or(false) {
+            fs::create_dir_all(&iwyu_dir).expect("Unable to create iwyu directory");
+            paths.push(&iwyu_dir);
+        }
+        build_user(user, &paths)
+    });
+
     match package_file.build {
-        Some(build) => {
-            let mut child = Command::new("bash")
-                .arg("-c")
-                .arg(format!("source /root/.bashrc\n\n{}", build.script))
-                .current_dir(&build_dir)
-                .env("OUT", &out_dir)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .expect("Failed to execute command");
-
-            let mut stdout = child.stdout.take().expect("Failed to capture stdout");
-            let mut stderr = child.stderr.take().expect("Failed to capture stderr");
-
-            std::thread::spawn(move || {
-                io::copy(&mut stdout, &mut io::stdout()).expect("Failed to copy stdout");
-            });
-
-            std::thread::spawn(move || {
-                io::copy(&mut stderr, &mut io::stderr()).expect("Failed to copy stderr");
-            });
-
-            let output = child.wait().expect("Failed to wait on child");
-
-            if !output.success() {
+        Some(ref build) => {
+            use_fakeroot = build.fakeroot.unwrap_or(false);
+            if use_fakeroot && !util::command_exists("fakeroot") {
+                panic!(
+                    "{}",
+                    PkgBuilderError::ToolMissing {
+                        tool: "fakeroot".to_string()
+                    }
+                );
+            }
+            // the namespace of allow-list is removed when this is dropped
+            let network = network::NetworkSandbox::setup(build);
+
+            let mut build_vars = build_env::build_environment(
+                &package_file,
+                build,
+                &build_dir,
+                &out_dir,
+                source_date_epoch,
+            );
+
+            if global_git_safe_dir || build.git_config_safe_directory.unwrap_or(false) {
+                eprintln!("Warning: git safe.directory checks are disabled for this build, this reduces security");
+                fs::write(&git_config, "[safe]\n\tdirectory = *\n")
+                    .expect("Unable to write git config");
+                build_vars.insert("GIT_CONFIG_GLOBAL".to_string(), git_config.clone());
+            }
+
+            // directories build_environment put in front of PATH
+            let path_prefix = [
+                (build.python_path.is_some(), python_dir.as_str()),
+                (build.iwyu.unwrap_or(false), iwyu_dir.as_str()),
+                (build.sccache.unwrap_or(false), sccache_dir.as_str()),
+            ]
+            .into_iter()
+            .filter_map(|(enabled, dir)| enabled.then_some(dir))
+            .collect::<Vec<_>>()
+            .join(":");
+            let env_deny_list = build_env::env_deny_list(build);
+            let runner = ScriptRunner {
+                vars: &build_vars,
+                fakeroot_state: use_fakeroot.then_some(fakeroot_state.as_str()),
+                user: build_user,
+                hermetic: build.hermetic.unwrap_or(false),
+                network: &network,
+                log: save_log.then_some(build_log.as_str()),
+                path_prefix: (!path_prefix.is_empty()).then_some(path_prefix.as_str()),
+                env_deny_list: &env_deny_list,
+            };
+
+            if build.audit_deps.unwrap_or(false) {
+                if let Err(err) = audit::audit_sources(&package_file, &build_dir, &audit_ignore_ids)
+                {
+                    panic!("{}", err);
+                }
+            }
+
+            // runs on every build, before the build script
+            if let Some(ref setup_script) = build.setup_script {
+                let setup_dir = match build.setup_workdir {
+                    Some(ref workdir) => format!("{}/{}", build_dir, workdir),
+                    None => build_dir.clone(),
+                };
+
+                println!("Running setup script in {}", setup_dir);
+                if !run_script(setup_script, &setup_dir, &runner) {
+                    eprintln!("Setup script failed");
+                    build_env::hermetic_failure_hint(build, &build_vars);
+                    panic!("Setup script failed");
+                }
+            }
+
+            timer.phase("build");
+            if !run_script(&build.script, &build_dir, &runner) {
                 eprintln!("Build script failed");
+                build_env::hermetic_failure_hint(build, &build_vars);
                 panic!("Build script failed");
             }
+
+            if let Some(ref check) = build.check {
+                timer.phase("check");
+                println!("Running check script");
+                if !run_script(check, &build_dir, &runner) {
+                    eprintln!("Check script failed");
+                    build_env::hermetic_failure_hint(build, &build_vars);
+                    panic!("Check script failed");
+                }
+            }
+
+            if build.sccache.unwrap_or(false) {
+                print_sccache_stats();
+            }
+
+            if build.coverage.unwrap_or(false) {
+                coverage::collect(build, &build_vars, &build_dir, &out_dir, &output_path);
+            }
+
+            if build.iwyu.unwrap_or(false) {
+                iwyu::collect(build, &build_dir, &output_path);
+            }
         }
         None => println!("No build script to execute"),
     }
 
     println!("Build script executed successfully, packaging...");
+    timer.phase("strip");
+
+    if let Some(ref build) = package_file.build {
+        postprocess::strip_la_files(build, &out_dir);
+        postprocess::cleanup_tempfiles(build, &out_dir);
+        postprocess::remove_docs(build, package_file.subpackage.as_deref(), &out_dir);
+        postprocess::filter_locales(build, &out_dir);
+        postprocess::generate_pkg_config(build, &package_file.package, &out_dir);
+        postprocess::remove_build_ids(build, &out_dir);
+        postprocess::compile_python_bytecode(build, &out_dir, source_date_epoch);
+    }
+
+    // the ignore file defaults to .pkgbuilderignore next to the PkgFile
+    let pkgfile_dir = std::path::Path::new(&file_path)
+        .parent()
+        .unwrap_or(std::path::Path::new("."));
+    let ignore_file = ignore_file.unwrap_or_else(|| {
+        pkgfile_dir
+            .join(
+                package_file
+                    .package
+                    .pkgfile_ignore
+                    .as_deref()
+                    .unwrap_or(".pkgbuilderignore"),
+            )
+            .to_string_lossy()
+            .to_string()
+    });
+    postprocess::apply_ignore_file(&ignore_file, &out_dir);
+
+    if let Some(ref output) = package_file.output {
+        if output.tarball_normalize_permissions.unwrap_or(false) {
+            normalize_permissions(&out_dir, output.tarball_keep_setuid.as_deref());
+        }
+    }
+
+    if let Some(ref build) = package_file.build {
+        if let Err(err) = postprocess::check_output_size(build, &out_dir) {
+            panic!("{}", err);
+        }
+
+        if let Err(err) = postprocess::check_rpath(build, &build_dir, &out_dir) {
+            panic!("{}", err);
+        }
+
+        if let Err(err) = abi::check_abi(build, &output_path, &build_dir, &out_dir) {
+            panic!("{}", err);
+        }
+    }
+
+    // subpackages generated from the build output
+    let mut generated_subpackages = Vec::new();
+    if let Some(ref build) = package_file.build {
+        generated_subpackages.extend(postprocess::split_subpackages(
+            build,
+            &package_file.package.name,
+            &out_dir,
+        ));
+
+        // SONAMEs belong to the package the library ends up in
+        let mut subpackages = package_file
+            .subpackage
+            .iter_mut()
+            .flatten()
+            .chain(generated_subpackages.iter_mut())
+            .collect::<Vec<_>>();
+        postprocess::auto_provides(build, &mut package_file.package, &mut subpackages, &out_dir);
+
+        // validates the SONAMEs added by auto_provides as well
+        let subpackages = package_file
+            .subpackage
+            .iter()
+            .flatten()
+            .chain(generated_subpackages.iter())
+            .collect::<Vec<_>>();
+        if let Err(err) =
+            postprocess::check_provides(build, &package_file.package, &subpackages, &out_dir)
+        {
+            panic!("{}", err);
+        }
+    }
+
+    let vendored_libs = package_file
+        .build
+        .as_ref()
+        .and_then(|build| build.vendored_exceptions.as_deref())
+        .unwrap_or_default();
+    let metadata = metadata::package_metadata(
+        &file_path,
+        &package_file.package,
+        &build_env::iso_8601(source_date_epoch),
+        vendored_libs,
+        package_file.subpackage.as_deref().unwrap_or_default(),
+        &generated_subpackages,
+    );
+
+    package_file
+        .subpackage
+        .get_or_insert_with(Vec::new)
+        .extend(generated_subpackages);
+
+    // a file can only be moved into one subpackage
+    if let Err(err) = verify::check_overlaps(&package_file, &out_dir, allow_overlap) {
+        panic!("{}", err);
+    }
+
+    // checked for all subpackages before any tarball is created
+    if let Err(err) = verify::check_subpackage_sizes(&package_file, &out_dir) {
+        panic!("{}", err);
+    }
+
+    // pipelines generating the metadata themselves leave out package.toml,
+    // such tarballs are not listed in the PACKAGES index
+    let include_pkgfile = package_file
+        .output
+        .as_ref()
+        .and_then(|output| output.include_pkgfile)
+        .unwrap_or(true);
+    if !include_pkgfile {
+        println!("Leaving package.toml out of the tarballs");
+    }
 
     // create final output directory
-    fs::create_dir_all(&output_path).expect("Unable to create output directory");
+    let split_arch_dir = package_file
+        .output
+        .as_ref()
+        .and_then(|output| output.split_arch_dir)
+        .unwrap_or(false);
+    let tarball_dir = if split_arch_dir {
+        format!("{}/{}", output_path, env::consts::ARCH)
+    } else {
+        output_path.clone()
+    };
+    fs::create_dir_all(&tarball_dir).expect("Unable to create output directory");
 
     if let Some(subpackages) = package_file.subpackage {
         for subpackage in subpackages {
             println!("Handling subpackage: {:#?}", subpackage);
+            timer.subpackage_phase(&subpackage.name);
 
             // create a seperate direcotry for subpackage
             let subpackage_dir = format!("{}/{}", &package_dir, subpackage.name);
@@ -113,33 +888,7 @@ fn main() {
             // move files to subpackage directory
             // files in a subpackage shouldn't be in the main package
             for file_selector in subpackage.files {
-                // the file_selector is a relative glob pattern
-                // so it must be expanded to get the actual file paths
-                let output = Command::new("sh")
-                    .arg("-c")
-                    .arg(format!(
-                        "shopt -s nullglob; shopt -s dotglob; echo {}{}",
-                        out_dir, file_selector
-                    ))
-                    .current_dir(&build_dir)
-                    .output()
-                    .expect("Failed to execute command");
-
-                if !output.status.success() {
-                    eprintln!(
-                        "Failed to expand file selector: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    );
-                    continue;
-                }
-
-                let files = String::from_utf8_lossy(&output.stdout);
-                let files = files.split_whitespace().collect::<Vec<&str>>();
-
-                for file in files {
-                    // remove the out directory from the file path
-                    let file = file.replace(&out_dir, "");
-
+                for file in expand_file_selector(&out_dir, &file_selector) {
                     // create the directory structure in the subpackage directory
                     let file_dir = file.rsplitn(2, '/').last().unwrap();
                     let file_dir = format!("{}/{}", &subpackage_dir, file_dir);
@@ -156,25 +905,29 @@ fn main() {
 
             println!("Moved files to subpackage directory: {}", subpackage_dir);
 
-            // Copy package file to subpackage directory
-            fs::copy(&file_path, &format!("{}/package.toml", subpackage_dir))
-                .expect("Unable to copy package file to subpackage directory");
+            // Write package metadata to subpackage directory
+            if include_pkgfile {
+                fs::write(format!("{}/package.toml", subpackage_dir), &metadata)
+                    .expect("Unable to write package metadata to subpackage directory");
+            }
+            if let Some(ref build) = package_file.build {
+                write_ldconfig_hook(build, &subpackage_dir, use_fakeroot);
+            }
+            let manifest_package = manifest::ManifestPackage {
+                name: subpackage.name.clone(),
+                version: package_file.package.version.clone(),
+                pkgrel: package_file.package.pkgrel.unwrap_or(1),
+            };
+            write_manifest(&subpackage_dir, &manifest_package, &source_urls);
 
             // Create a tarball of the subpackage directory
-            let tarball_name = format!("{}/{}.tar.gz", &output_path, subpackage.name);
-            let output = Command::new("tar")
-                .arg("-czf")
-                .arg(&tarball_name)
-                .arg("./")
-                .current_dir(&subpackage_dir)
-                .output()
-                .expect("Failed to create tarball");
-
-            if !output.status.success() {
-                eprintln!(
-                    "Failed to create tarball: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                );
+            let tarball_name = format!("{}/{}.tar.gz", &tarball_dir, subpackage.name);
+            if !create_tarball(
+                &subpackage_dir,
+                &tarball_name,
+                package_file.output.as_ref(),
+                use_fakeroot.then_some(fakeroot_state.as_str()),
+            ) {
                 continue;
             }
 
@@ -182,21 +935,57 @@ fn main() {
 
             // Remove subpackage directory
             fs::remove_dir_all(&subpackage_dir).expect("Unable to remove subpackage directory");
-
-            // Copy tarball to final output directory
-            fs::copy(&tarball_name, &format!("{}/{}", &output_path, tarball_name))
-                .expect("Unable to copy tarball to output directory");
         }
     }
 
+    timer.phase("package_main");
+
     // Move the remaining files from the out directory to the package directory
     // in a subfolder named after the package name
     Command::new("mv")
         .arg(&out_dir)
-        .arg(&format!("{}/{}", package_dir, package_file.package.name))
+        .arg(format!("{}/{}", package_dir, package_file.package.name))
         .output()
         .expect("Failed to move files from out directory to package directory");
 
+    let main_package_dir = format!("{}/{}", package_dir, package_file.package.name);
+    if include_pkgfile {
+        fs::write(format!("{}/package.toml", main_package_dir), &metadata)
+            .expect("Unable to write package metadata to package directory");
+    }
+    if let Some(ref build) = package_file.build {
+        write_hook_scripts(build, &main_package_dir);
+        write_ldconfig_hook(build, &main_package_dir, use_fakeroot);
+    }
+    if save_log && std::path::Path::new(&build_log).exists() {
+        compress_build_log(&build_log, &main_package_dir);
+    }
+    let manifest_package = manifest::ManifestPackage {
+        name: package_file.package.name.clone(),
+        version: package_file.package.version.clone(),
+        pkgrel: package_file.package.pkgrel.unwrap_or(1),
+    };
+    write_manifest(&main_package_dir, &manifest_package, &source_urls);
+
+    let tarball_name = format!("{}/{}.tar.gz", &tarball_dir, package_file.package.name);
+    if create_tarball(
+        &main_package_dir,
+        &tarball_name,
+        package_file.output.as_ref(),
+        use_fakeroot.then_some(fakeroot_state.as_str()),
+    ) {
+        println!("Created tarball for package: {}", tarball_name);
+    }
+
+    if !no_index {
+        repo::write_index(&tarball_dir, &repo::generate_index(&tarball_dir));
+        if split_arch_dir {
+            repo::write_repo_toml(&output_path);
+        }
+    }
+
+    drop(worktrees);
+
     // remove build directory
     fs::remove_dir_all(&build_dir).expect("Unable to remove build directory");
     println!("Removed build directory: {}", build_dir);
@@ -207,34 +996,435 @@ fn main() {
 
     // Out directory got moved into package directory, does not have to be deleted
 
+    if use_fakeroot {
+        fs::remove_file(&fakeroot_state).expect("Unable to remove fakeroot state file");
+    }
+
+    if std::path::Path::new(&git_config).exists() {
+        fs::remove_file(&git_config).expect("Unable to remove git config");
+    }
+
+    if std::path::Path::new(&build_log).exists() {
+        fs::remove_file(&build_log).expect("Unable to remove build log");
+    }
+
+    if std::path::Path::new(&python_dir).exists() {
+        fs::remove_dir_all(&python_dir).expect("Unable to remove python directory");
+    }
+
+    if std::path::Path::new(&iwyu_dir).exists() {
+        fs::remove_dir_all(&iwyu_dir).expect("Unable to remove iwyu directory");
+    }
+
+    if std::path::Path::new(&sccache_dir).exists() {
+        fs::remove_dir_all(&sccache_dir).expect("Unable to remove sccache directory");
+    }
+
+    if perf_events {
+        timer.report(&package_file.package.name, &output_path);
+    }
+
     println!("Package built successfully");
 }
 
-fn setup_build_environment(pkgfile: &PkgFile) -> (String, String, String) {
+// how the scripts of a build are run
+struct ScriptRunner<'a> {
+    vars: &'a BTreeMap<String, String>,
+    // fakeroot state file, None when fakeroot isn't used
+    fakeroot_state: Option<&'a str>,
+    // uid and gid of force_user
+    user: Option<(u32, u32)>,
+    // hermetic scripts don't source the host's .bashrc
+    hermetic: bool,
+    network: &'a network::NetworkSandbox,
+    // the output is also appended to this file with --save-log
+    log: Option<&'a str>,
+    // kept in front of PATH when .bashrc changes it
+    path_prefix: Option<&'a str>,
+    // variables unset again after .bashrc
+    env_deny_list: &'a [String],
+}
+
+// runs a PkgFile script with bash, optionally under fakeroot
+// or as another user, and forwards its output
+fn run_script(script: &str, working_dir: &str, runner: &ScriptRunner) -> bool {
+    let network = runner.network;
+    let mut command = match runner.fakeroot_state {
+        Some(fakeroot_state) => {
+            let mut command = network.command("fakeroot", runner.user);
+            command.arg("-s").arg(fakeroot_state).arg("--").arg("bash");
+            command
+        }
+        None => network.command("bash", runner.user),
+    };
+
+    // root's .bashrc is not readable by other users
+    let mut preamble = "source /root/.bashrc\n\n".to_string();
+    if runner.user.is_some() || runner.hermetic {
+        preamble = String::new();
+    } else {
+        preamble.push_str(&build_env::unset_denied_vars(runner.env_deny_list));
+        if let Some(prefix) = runner.path_prefix {
+            preamble.push_str(&format!("export PATH=\"{}:$PATH\"\n\n", prefix));
+        }
+    }
+
+    let mut child = command
+        .arg("-c")
+        .arg(format!("{}{}{}", network.preamble(), preamble, script))
+        .current_dir(working_dir)
+        .env_clear()
+        .envs(runner.vars)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+
+    let stdout = child.stdout.take().expect("Failed to capture stdout");
+    let stderr = child.stderr.take().expect("Failed to capture stderr");
+
+    let log = runner.log.map(|log| log.to_string());
+    let stdout = std::thread::spawn({
+        let log = log.clone();
+        move || forward_output(stdout, io::stdout(), log.as_deref())
+    });
+    let stderr = std::thread::spawn(move || forward_output(stderr, io::stderr(), log.as_deref()));
+
+    let output = child.wait().expect("Failed to wait on child");
+    stdout.join().expect("Failed to copy stdout");
+    stderr.join().expect("Failed to copy stderr");
+    output.success()
+}
+
+// copies the output of a script, and into the log file if there is one
+fn forward_output(mut from: impl Read, mut to: impl Write, log: Option<&str>) {
+    let mut log = log.map(|log| {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .expect("Unable to open build log")
+    });
+
+    let mut buffer = [0; 8192];
+    loop {
+        let read = from.read(&mut buffer).expect("Failed to read output");
+        if read == 0 {
+            break;
+        }
+        to.write_all(&buffer[..read])
+            .expect("Failed to write output");
+        if let Some(ref mut log) = log {
+            log.write_all(&buffer[..read])
+                .expect("Unable to write build log");
+        }
+    }
+}
+
+// compresses the build log into build.log.zst in the package directory
+fn compress_build_log(build_log: &str, package_dir: &str) {
+    if !util::command_exists("zstd") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "zstd".to_string()
+            }
+        );
+    }
+
+    let output = Command::new("zstd")
+        .arg("-q")
+        .arg("-f")
+        .arg(build_log)
+        .arg("-o")
+        .arg(format!("{}/build.log.zst", package_dir))
+        .output()
+        .expect("Failed to execute command");
+    if !output.status.success() {
+        panic!(
+            "Unable to compress build log: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+// resolves force_user to a uid and gid and hands the build directories to it,
+// None if pkgbuilder isn't running as root
+fn build_user(name: &str, paths: &[&str]) -> Option<(u32, u32)> {
+    if !util::is_root() {
+        eprintln!("Warning: not running as root, ignoring force_user {}", name);
+        return None;
+    }
+
+    let ids = util::user_ids(name).unwrap_or_else(|| {
+        panic!(
+            "{}",
+            PkgBuilderError::UserNotFound {
+                name: name.to_string()
+            }
+        )
+    });
+
+    for path in paths {
+        // fakeroot only writes its state file on exit
+        if !std::path::Path::new(path).exists() {
+            File::create(path).expect("Unable to create file");
+        }
+
+        let output = Command::new("chown")
+            .arg("-R")
+            .arg(format!("{}:{}", ids.0, ids.1))
+            .arg(path)
+            .output()
+            .expect("Failed to execute command");
+        if !output.status.success() {
+            panic!(
+                "Unable to hand {} to {}: {}",
+                path,
+                name,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+    }
+
+    println!("Running build scripts as {}", name);
+    Some(ids)
+}
+
+fn print_sccache_stats() {
+    let output = Command::new("sccache")
+        .arg("--show-stats")
+        .output()
+        .expect("Failed to execute command");
+
+    if output.status.success() {
+        println!(
+            "sccache statistics:\n{}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    } else {
+        eprintln!(
+            "Failed to read sccache statistics: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+// writes the FILES manifest into the root of a package directory
+fn write_manifest(package_dir: &str, package: &manifest::ManifestPackage, sources: &[String]) {
+    let entries = manifest::generate(package_dir);
+    let contents = manifest::render_package(package)
+        + &manifest::render_sources(sources)
+        + &manifest::render(&entries);
+    fs::write(format!("{}/FILES", package_dir), contents).expect("Unable to write FILES manifest");
+}
+
+// writes the install hooks of the package into the root of the package directory
+fn write_hook_scripts(build: &PkgFileBuild, package_dir: &str) {
+    let hooks = [
+        ("pre_upgrade.sh", &build.pre_upgrade),
+        ("post_upgrade.sh", &build.post_upgrade),
+    ];
+
+    for (file_name, script) in hooks {
+        if let Some(script) = script {
+            fs::write(format!("{}/{}", package_dir, file_name), script)
+                .expect("Unable to write hook script");
+        }
+    }
+}
+
+// appends ldconfig to post_install.sh and post_upgrade.sh
+// when the package has shared libraries,
+// with fakeroot only the links in the package's directories are updated
+// and the system cache is left alone
+fn write_ldconfig_hook(build: &PkgFileBuild, package_dir: &str, fakeroot: bool) {
+    if build.ldconfig_update == Some(false) {
+        return;
+    }
+    // without an explicit ldconfig_update only versioned libraries enable it
+    let explicit = build.ldconfig_update == Some(true);
+
+    let library_dirs = ["lib", "usr/lib"]
+        .into_iter()
+        .filter(|dir| {
+            fs::read_dir(format!("{}/{}", package_dir, dir))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .any(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    name.contains(".so.") || (explicit && name.ends_with(".so"))
+                })
+        })
+        .collect::<Vec<_>>();
+    if library_dirs.is_empty() {
+        return;
+    }
+
+    // hooks run from the root the package is installed to,
+    // -r keeps ldconfig from updating the cache of the host when that isn't /
+    let command = if fakeroot {
+        format!("ldconfig -n {}\n", library_dirs.join(" "))
+    } else {
+        "ldconfig -r .\n".to_string()
+    };
+
+    // after write_hook_scripts, so a post_upgrade of the PkgFile is kept
+    for file_name in ["post_install.sh", "post_upgrade.sh"] {
+        let hook = format!("{}/{}", package_dir, file_name);
+        let mut script = fs::read_to_string(&hook).unwrap_or_default();
+        if !script.is_empty() && !script.ends_with('\n') {
+            script.push('\n');
+        }
+        script.push_str(&command);
+        fs::write(&hook, script).expect("Unable to write hook script");
+        println!("Added ldconfig to {}", file_name);
+    }
+}
+
+// creates a gzip compressed tarball from the contents of a directory
+fn create_tarball(
+    source_dir: &str,
+    tarball_name: &str,
+    output: Option<&PkgFileOutput>,
+    fakeroot_state: Option<&str>,
+) -> bool {
+    let mut command = match fakeroot_state {
+        Some(fakeroot_state) => {
+            // restore the ownership recorded while running the build script
+            let mut command = Command::new("fakeroot");
+            command.arg("-i").arg(fakeroot_state).arg("--").arg("tar");
+            command
+        }
+        None => Command::new("tar"),
+    };
+
+    let owner = output.and_then(|output| output.tarball_owner.as_deref());
+    let group = output.and_then(|output| output.tarball_group.as_deref());
+
+    // with fakeroot the recorded ownership is used unless it is overridden explicitly
+    if fakeroot_state.is_none() || owner.is_some() {
+        command.arg(format!("--owner={}", owner.unwrap_or("root")));
+    }
+    if fakeroot_state.is_none() || group.is_some() {
+        command.arg(format!("--group={}", group.unwrap_or("root")));
+    }
+
+    let output = command
+        .arg("-czf")
+        .arg(tarball_name)
+        .arg("./")
+        .current_dir(source_dir)
+        .output()
+        .expect("Failed to create tarball");
+
+    if !output.status.success() {
+        eprintln!(
+            "Failed to create tarball: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return false;
+    }
+
+    true
+}
+
+// removes setuid and setgid bits from all files in the out directory
+// except for the ones listed in keep
+fn normalize_permissions(out_dir: &str, keep: Option<&[String]>) {
+    for file in util::walk_files(std::path::Path::new(out_dir)) {
+        let metadata = file
+            .symlink_metadata()
+            .expect("Unable to read file metadata");
+        if metadata.is_symlink() {
+            continue;
+        }
+
+        let mode = metadata.permissions().mode();
+        if mode & 0o6000 == 0 {
+            continue;
+        }
+
+        let relative = file.to_string_lossy().replace(out_dir, "");
+        if keep.is_some_and(|keep| keep.contains(&relative)) {
+            continue;
+        }
+
+        println!("Removing setuid/setgid bits from {}", relative);
+        fs::set_permissions(&file, fs::Permissions::from_mode(mode & !0o6000))
+            .expect("Unable to set file permissions");
+    }
+}
+
+// expands a subpackage file selector (a glob pattern relative to the out directory)
+// and returns the matched paths relative to the out directory
+fn expand_file_selector(out_dir: &str, file_selector: &str) -> Vec<String> {
+    let output = Command::new("bash")
+        .arg("-c")
+        .arg(format!(
+            "shopt -s nullglob; shopt -s dotglob; echo {}{}",
+            out_dir, file_selector
+        ))
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        eprintln!(
+            "Failed to expand file selector: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return vec![];
+    }
+
+    let mut files: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        // remove the out directory from the file path
+        .map(|file| file.replace(out_dir, ""))
+        .collect();
+
+    // compiled bytecode belongs to whoever selects the python source
+    let bytecode = files
+        .iter()
+        .filter(|file| file.ends_with(".py"))
+        .flat_map(|file| postprocess::bytecode_files(out_dir, file))
+        .collect::<Vec<_>>();
+    for file in bytecode {
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+
+    files
+}
+
+// the build, out and package directories of a build started now
+fn build_dirs(pkgfile: &PkgFile) -> (String, String, String) {
     // get unix timestamp
     let timestamp = chrono::Utc::now().timestamp();
 
-    // create build directory in /tmp
     let build_dir = format!(
         "/tmp/pkgbuilder/build_{}_{}_{}",
         pkgfile.package.name, pkgfile.package.version, timestamp
     );
+    let out_dir = format!("{}_out", build_dir);
+    let package_dir = format!("{}_package", build_dir);
+
+    (build_dir, out_dir, package_dir)
+}
+
+fn setup_build_environment(pkgfile: &PkgFile, reproducible: bool) -> (String, String, String) {
+    let (build_dir, out_dir, package_dir) = build_dirs(pkgfile);
+
+    // create build directory in /tmp
     fs::create_dir_all(&build_dir).expect("Unable to create build directory");
     println!("Created build directory: {}", build_dir);
 
     // create out directory in /tmp
-    let out_dir = format!(
-        "/tmp/pkgbuilder/build_{}_{}_{}_out",
-        pkgfile.package.name, pkgfile.package.version, timestamp
-    );
     fs::create_dir_all(&out_dir).expect("Unable to create out directory");
     println!("Created out directory: {}", out_dir);
 
     // create package directory in /tmp
-    let package_dir = format!(
-        "/tmp/pkgbuilder/build_{}_{}_{}_package",
-        pkgfile.package.name, pkgfile.package.version, timestamp
-    );
     fs::create_dir_all(&package_dir).expect("Unable to create package directory");
     println!("Created package directory: {}", package_dir);
 
@@ -242,25 +1432,169 @@ fn setup_build_environment(pkgfile: &PkgFile) -> (String, String, String) {
         Some(ref sources) => {
             for source in sources {
                 let source_url = &source.source;
-                let destination = match source.destination {
-                    Some(ref destination) => format!("{}{}", build_dir.clone(), destination),
-                    None => build_dir.clone(),
-                };
+                let destination = source.destination_dir(&build_dir);
+                if source.archive_extension().is_some() {
+                    fs::create_dir_all(&destination)
+                        .expect("Unable to create source destination directory");
+                }
+
+                if source.vcs.as_deref() == Some("rsync") || source_url.starts_with("rsync://") {
+                    if reproducible {
+                        eprintln!(
+                            "Warning: rsync source {} can not be fetched reproducibly",
+                            source_url
+                        );
+                    }
+
+                    if !util::command_exists("rsync") {
+                        panic!(
+                            "{}",
+                            PkgBuilderError::VcsToolMissing {
+                                vcs: "rsync".to_string()
+                            }
+                        );
+                    }
+
+                    println!("Syncing {} into {}", source_url, &destination);
+
+                    let output = Command::new("rsync")
+                        .arg("-a")
+                        .arg("--delete")
+                        .args(source.rsync_options.iter().flatten())
+                        .arg(source_url)
+                        .arg(&destination)
+                        .output()
+                        .expect("Failed to execute command");
+
+                    if !output.status.success() {
+                        eprintln!("Rsync failed: {}", String::from_utf8_lossy(&output.stderr));
+                    }
+
+                    continue;
+                }
+
+                if source.vcs.as_deref() != Some("hg")
+                    && (source.hg_rev.is_some()
+                        || source.hg_tag.is_some()
+                        || source.hg_branch.is_some())
+                {
+                    eprintln!(
+                        "Warning: hg_rev, hg_tag and hg_branch are only used with vcs = \"hg\", ignoring them for {}",
+                        source_url
+                    );
+                }
+
+                if source.vcs.as_deref() == Some("hg") {
+                    if !util::command_exists("hg") {
+                        panic!(
+                            "{}",
+                            PkgBuilderError::VcsToolMissing {
+                                vcs: "hg".to_string()
+                            }
+                        );
+                    }
+
+                    let hg_branch = match (&source.hg_branch, &source.git_ref) {
+                        (Some(hg_branch), _) => Some(hg_branch),
+                        (None, Some(git_ref)) => {
+                            eprintln!(
+                                "Warning: git_ref is deprecated for hg sources, use hg_branch instead"
+                            );
+                            Some(git_ref)
+                        }
+                        (None, None) => None,
+                    };
+
+                    // the most specific revision wins: hg_rev, hg_tag, hg_branch, default head
+                    let revision = match (&source.hg_rev, &source.hg_tag, hg_branch) {
+                        (Some(hg_rev), _, _) => vec!["--updaterev", hg_rev],
+                        (None, Some(hg_tag), _) => vec!["--updaterev", hg_tag],
+                        (None, None, Some(hg_branch)) => vec!["--branch", hg_branch],
+                        (None, None, None) => vec![],
+                    };
+
+                    println!("Cloning {} into {}", source_url, &destination);
+
+                    let output = Command::new("hg")
+                        .arg("clone")
+                        .args(revision)
+                        .arg(source_url)
+                        .arg(&destination)
+                        .output()
+                        .expect("Failed to execute command");
+
+                    if !output.status.success() {
+                        eprintln!(
+                            "Hg clone failed: {}",
+                            String::from_utf8_lossy(&output.stderr)
+                        );
+                    }
+
+                    continue;
+                }
+
+                if source.vcs.as_deref() == Some("bzr") {
+                    checkout_bzr(source, &destination);
+                    continue;
+                }
 
                 if source_url.ends_with(".git") {
+                    if source.git_worktree.unwrap_or(false) {
+                        let revision = source.git_commit.as_deref().or(source.git_ref.as_deref());
+                        if !worktree::add(source_url, &destination, revision) {
+                            panic!("Unable to add a worktree of {}", source_url);
+                        }
+                        continue;
+                    }
+
                     println!("Cloning {} into {}", source_url, &destination);
+                    if source.canonical_url() != source_url {
+                        println!("{} is a mirror of {}", source_url, source.canonical_url());
+                    }
+
+                    let bundle = source
+                        .git_bundle
+                        .as_deref()
+                        .map(|bundle| bundle.strip_prefix("file://").unwrap_or(bundle).to_string());
+                    let bundle = match bundle {
+                        Some(bundle) if std::path::Path::new(&bundle).is_file() => {
+                            println!("Cloning from git bundle {}", bundle);
+                            Some(bundle)
+                        }
+                        Some(bundle) => {
+                            eprintln!(
+                                "Warning: git bundle {} doesn't exist, cloning {}",
+                                bundle, source_url
+                            );
+                            None
+                        }
+                        None => None,
+                    };
+
+                    // don't copy all the history
+                    let history = match (source.git_depth, &source.git_shallow_since) {
+                        (Some(_), Some(_)) => panic!(
+                            "{}",
+                            PkgBuilderError::ConflictingSourceFields {
+                                url: source_url.to_string(),
+                                fields: "git_depth and git_shallow_since".to_string(),
+                            }
+                        ),
+                        (_, Some(since)) => vec![format!("--shallow-since={}", since)],
+                        // a bundle is a local file, shallow clones don't apply
+                        _ if bundle.is_some() => vec![],
+                        (depth, None) => vec![format!("--depth={}", depth.unwrap_or(1))],
+                    };
 
                     let output = Command::new("git")
                         .arg("clone")
-                        // don't copy all the history
-                        .arg("--depth")
-                        .arg("1")
+                        .args(history)
                         // if a git_ref is specified, add the --branch flag
                         .args(match source.git_ref {
                             Some(ref git_ref) => vec!["--branch", git_ref],
                             None => vec![],
                         })
-                        .arg(source_url)
+                        .arg(bundle.as_deref().unwrap_or(source_url))
                         .arg(destination.clone())
                         .output()
                         .expect("Failed to execute command");
@@ -295,75 +1629,62 @@ fn setup_build_environment(pkgfile: &PkgFile) -> (String, String, String) {
                     || source_url.ends_with(".tgz")
                     || source_url.ends_with(".tar.bz2")
                     || source_url.ends_with(".tar.xz")
+                    || source_url.ends_with(".tar.zst")
                 {
-                    println!("Downloading {} into {}", source_url, &destination);
-
-                    let output = Command::new("curl")
-                        .arg("-L")
-                        .arg(source_url)
-                        .arg("-o")
-                        .arg(format!("{}.tmpdownload", &destination))
-                        .output()
-                        .expect("Failed to execute command");
-
-                    if !output.status.success() {
-                        eprintln!(
-                            "Download failed: {}",
-                            String::from_utf8_lossy(&output.stderr)
-                        );
+                    if !download::download_source(source, &format!("{}.tmpdownload", &destination))
+                    {
+                        panic!("Unable to download {}", source_url);
                     }
 
                     println!("Extracting {} into {}", source_url, &destination);
 
-                    let output = Command::new("tar")
-                        .arg("-xvf")
-                        .arg(format!("{}.tmpdownload", &destination))
-                        .arg("-C")
-                        .arg(&destination)
-                        .output()
-                        .expect("Failed to execute command");
-
-                    if !output.status.success() {
-                        eprintln!(
-                            "Extraction failed: {}",
-                            String::from_utf8_lossy(&output.stderr)
-                        );
+                    if let Err(err) = archive::extract_tarball(
+                        &format!("{}.tmpdownload", &destination),
+                        &destination,
+                        &[],
+                    ) {
+                        eprintln!("{}", err);
                     }
                 }
 
                 if source_url.ends_with(".zip") {
-                    println!("Downloading {} into {}", source_url, &destination);
-
-                    let output = Command::new("curl")
-                        .arg("-L")
-                        .arg(source_url)
-                        .arg("-o")
-                        .arg(format!("{}.tmpdownload", &destination))
-                        .output()
-                        .expect("Failed to execute command");
-
-                    if !output.status.success() {
-                        eprintln!(
-                            "Download failed: {}",
-                            String::from_utf8_lossy(&output.stderr)
-                        );
+                    if !download::download_source(source, &format!("{}.tmpdownload", &destination))
+                    {
+                        panic!("Unable to download {}", source_url);
                     }
 
                     println!("Extracting {} into {}", source_url, &destination);
 
-                    let output = Command::new("unzip")
-                        .arg("-o")
-                        .arg(format!("{}.tmpdownload", &destination))
-                        .arg("-d")
-                        .arg(&destination)
-                        .output()
-                        .expect("Failed to execute command");
+                    if let Some(ref password) = source.archive_password {
+                        if password.is_plaintext() {
+                            eprintln!(
+                                "Warning: archive_password for {} is stored as plaintext, use $ENV{{VAR}} instead",
+                                source_url
+                            );
+                        }
 
-                    if !output.status.success() {
-                        eprintln!(
-                            "Extraction failed: {}",
-                            String::from_utf8_lossy(&output.stderr)
-                        );
+                        if let Err(err) = archive::extract_encrypted_zip(
+                            &format!("{}.tmpdownload", &destination),
+                            &destination,
+                            &password.resolve(),
+                        ) {
+                            eprintln!("{}", err);
+                        }
+                    } else {
+                        let output = Command::new("unzip")
+                            .arg("-o")
+                            .arg(format!("{}.tmpdownload", &destination))
+                            .arg("-d")
+                            .arg(&destination)
+                            .output()
+                            .expect("Failed to execute command");
+
+                        if !output.status.success() {
+                            eprintln!(
+                                "Extraction failed: {}",
+                                String::from_utf8_lossy(&output.stderr)
+                            );
+                        }
                     }
                 }
             }
@@ -373,5 +1694,126 @@ fn setup_build_environment(pkgfile: &PkgFile) -> (String, String, String) {
 
     println!("Build environment setup successfully");
 
-    return (build_dir, out_dir, package_dir);
+    (build_dir, out_dir, package_dir)
+}
+
+// branches a bzr source into destination, an existing branch is pulled instead
+fn checkout_bzr(source: &PkgFileSource, destination: &str) {
+    if !util::command_exists("bzr") {
+        panic!(
+            "{}",
+            PkgBuilderError::VcsToolMissing {
+                vcs: "bzr".to_string()
+            }
+        );
+    }
+
+    // git_commit is a revision id, git_ref a revision number or spec
+    let revision = match (&source.git_commit, &source.git_ref) {
+        (Some(git_commit), _) => {
+            vec!["-r".to_string(), format!("revid:{}", git_commit)]
+        }
+        (None, Some(git_ref)) => vec!["-r".to_string(), git_ref.clone()],
+        (None, None) => vec![],
+    };
+
+    // an existing branch is updated instead of branched again
+    let mut command = Command::new("bzr");
+    if std::path::Path::new(destination).join(".bzr").is_dir() {
+        println!("Updating {} in {}", source.source, destination);
+        command
+            .arg("pull")
+            .args(revision)
+            .arg(&source.source)
+            .current_dir(destination);
+    } else {
+        println!("Branching {} into {}", source.source, destination);
+        command
+            .arg("branch")
+            .arg("--use-existing-dir")
+            .args(revision)
+            .arg(&source.source)
+            .arg(destination);
+    }
+
+    let output = command.output().expect("Failed to execute command");
+    if !output.status.success() {
+        eprintln!(
+            "Bzr checkout failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn bzr(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("bzr")
+            .args(args)
+            .current_dir(dir)
+            .env("BZR_EMAIL", "pkgbuilder <pkgbuilder@localhost>")
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "bzr {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    fn bzr_source(upstream: &Path, revision: &str) -> PkgFileSource {
+        let mut source = toml::Table::new();
+        source.insert("source".into(), upstream.display().to_string().into());
+        source.insert("vcs".into(), "bzr".into());
+        if !revision.is_empty() {
+            source.insert("git_commit".into(), revision.into());
+        }
+        source.try_into().unwrap()
+    }
+
+    #[test]
+    fn bzr_branch_revision_and_pull() {
+        if !util::command_exists("bzr") {
+            eprintln!("Skipping, bzr is not installed");
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("pkgbuilder-bzr-{}", std::process::id()));
+        let upstream = dir.join("upstream");
+        fs::create_dir_all(&upstream).unwrap();
+        bzr(&upstream, &["init"]);
+        fs::write(upstream.join("first"), "first\n").unwrap();
+        bzr(&upstream, &["add", "first"]);
+        bzr(&upstream, &["commit", "-m", "first"]);
+        let first = bzr(&upstream, &["revision-info", "-r", "1"]);
+        let first = first.split_whitespace().nth(1).unwrap().to_string();
+        fs::write(upstream.join("second"), "second\n").unwrap();
+        bzr(&upstream, &["add", "second"]);
+        bzr(&upstream, &["commit", "-m", "second"]);
+
+        // a fresh branch gets the latest revision
+        let latest = dir.join("latest");
+        fs::create_dir_all(&latest).unwrap();
+        checkout_bzr(&bzr_source(&upstream, ""), latest.to_str().unwrap());
+        assert!(latest.join("second").is_file());
+
+        // git_commit selects a revision id
+        let pinned = dir.join("pinned");
+        fs::create_dir_all(&pinned).unwrap();
+        checkout_bzr(&bzr_source(&upstream, &first), pinned.to_str().unwrap());
+        assert!(pinned.join("first").is_file());
+        assert!(!pinned.join("second").exists());
+
+        // an existing branch is pulled
+        checkout_bzr(&bzr_source(&upstream, ""), pinned.to_str().unwrap());
+        let pulled = pinned.join("second").is_file();
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(pulled);
+    }
 }
@@ -0,0 +1,124 @@
+use crate::util;
+use std::path::{Path, PathBuf};
+
+// The FILES manifest stored in the root of every package tarball.
+// Each line describes one file of the package: `<sha256> <size> <path>`.
+// Symlinks are recorded with `-` as checksum and a size of 0.
+// Lines starting with `#` are comments, `# package <name> <version> <pkgrel>`
+// identifies the package, so a repository can index tarballs built
+// without package.toml, and `# source <url>` records the url a release
+// asset or mirrored git source comes from.
+
+// files in the root of a package tarball that are not installed
+pub const METADATA_FILES: [&str; 7] = [
+    "package.toml",
+    "FILES",
+    "build.log.zst",
+    "pre_install.sh",
+    "post_install.sh",
+    "pre_upgrade.sh",
+    "post_upgrade.sh",
+];
+
+#[derive(Debug)]
+pub struct ManifestPackage {
+    pub name: String,
+    pub version: String,
+    pub pkgrel: u32,
+}
+
+#[derive(Debug)]
+pub struct ManifestEntry {
+    pub sha256: String,
+    pub size: u64,
+    // absolute path of the installed file, e.g. /usr/bin/foo
+    pub path: String,
+}
+
+// creates the manifest of a package directory, metadata files are skipped
+pub fn generate(package_dir: &str) -> Vec<ManifestEntry> {
+    let metadata_files = METADATA_FILES
+        .iter()
+        .map(|file| format!("/{}", file))
+        .collect::<Vec<_>>();
+
+    let mut files = util::walk_files(Path::new(package_dir))
+        .into_iter()
+        .filter(|file| {
+            let relative = file.to_string_lossy().replace(package_dir, "");
+            !metadata_files.contains(&relative)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let (symlinks, regular): (Vec<PathBuf>, Vec<PathBuf>) =
+        files.into_iter().partition(|file| file.is_symlink());
+
+    let checksums = util::sha256_files(&regular);
+
+    let mut entries = regular
+        .iter()
+        .zip(checksums)
+        .map(|(file, sha256)| ManifestEntry {
+            sha256,
+            size: file.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            path: file.to_string_lossy().replace(package_dir, ""),
+        })
+        .chain(symlinks.iter().map(|file| ManifestEntry {
+            sha256: "-".to_string(),
+            size: 0,
+            path: file.to_string_lossy().replace(package_dir, ""),
+        }))
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    entries
+}
+
+pub fn render(entries: &[ManifestEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{} {} {}\n", entry.sha256, entry.size, entry.path))
+        .collect()
+}
+
+pub fn render_package(package: &ManifestPackage) -> String {
+    format!(
+        "# package {} {} {}\n",
+        package.name, package.version, package.pkgrel
+    )
+}
+
+// the package line of a manifest, None for manifests written before it existed
+pub fn parse_package(contents: &str) -> Option<ManifestPackage> {
+    contents.lines().find_map(|line| {
+        let mut parts = line.strip_prefix("# package ")?.split(' ');
+        Some(ManifestPackage {
+            name: parts.next()?.to_string(),
+            version: parts.next()?.to_string(),
+            pkgrel: parts.next()?.parse().ok()?,
+        })
+    })
+}
+
+pub fn render_sources(sources: &[String]) -> String {
+    sources
+        .iter()
+        .map(|source| format!("# source {}\n", source))
+        .collect()
+}
+
+pub fn parse(contents: &str) -> Vec<ManifestEntry> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            Some(ManifestEntry {
+                sha256: parts.next()?.to_string(),
+                size: parts.next()?.parse().ok()?,
+                path: parts.next()?.to_string(),
+            })
+        })
+        .collect()
+}
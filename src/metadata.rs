@@ -0,0 +1,100 @@
+use crate::{PkgFilePackage, PkgFileSubPackage};
+use std::fs;
+
+// Renders the package.toml embedded in every tarball.
+// It is the PkgFile with build information added to the [package] table
+// and the subpackages generated during the build appended.
+// The name and provides are taken from the package as they can change
+// during the build, e.g. for sanitizer builds or with auto_provides,
+// which also sets the provides of subpackages.
+// source_package is always set, it defaults to the name in the PkgFile.
+pub fn package_metadata(
+    file_path: &str,
+    package_info: &PkgFilePackage,
+    build_date: &str,
+    vendored_libs: &[String],
+    subpackages: &[PkgFileSubPackage],
+    generated_subpackages: &[PkgFileSubPackage],
+) -> String {
+    let contents = fs::read_to_string(file_path).expect("Unable to read the file");
+    let mut pkgfile: toml::Table =
+        toml::from_str(&contents).expect("Unable to parse the TOML file");
+
+    // passwords of encrypted sources must not be published
+    for source in pkgfile
+        .get_mut("source")
+        .and_then(|sources| sources.as_array_mut())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(source) = source.as_table_mut() {
+            source.remove("archive_password");
+        }
+    }
+
+    let package = pkgfile
+        .get_mut("package")
+        .and_then(|package| package.as_table_mut())
+        .expect("PkgFile has no [package] table");
+    package.insert(
+        "name".to_string(),
+        toml::Value::String(package_info.name.clone()),
+    );
+    if let Some(ref source_package) = package_info.source_package {
+        package.insert(
+            "source_package".to_string(),
+            toml::Value::String(source_package.clone()),
+        );
+    }
+    if let Some(ref provides) = package_info.provides {
+        package.insert(
+            "provides".to_string(),
+            toml::Value::try_from(provides).expect("Unable to serialize provides"),
+        );
+    }
+    package.insert(
+        "build_date".to_string(),
+        toml::Value::String(build_date.to_string()),
+    );
+    if !vendored_libs.is_empty() {
+        package.insert(
+            "vendored_libs".to_string(),
+            toml::Value::try_from(vendored_libs).expect("Unable to serialize vendored_libs"),
+        );
+    }
+
+    for table in pkgfile
+        .get_mut("subpackage")
+        .and_then(|subpackages| subpackages.as_array_mut())
+        .into_iter()
+        .flatten()
+        .filter_map(|subpackage| subpackage.as_table_mut())
+    {
+        let provides = subpackages
+            .iter()
+            .find(|subpackage| {
+                table.get("name").and_then(|name| name.as_str()) == Some(&subpackage.name)
+            })
+            .and_then(|subpackage| subpackage.provides.as_ref());
+        if let Some(provides) = provides {
+            table.insert(
+                "provides".to_string(),
+                toml::Value::try_from(provides).expect("Unable to serialize provides"),
+            );
+        }
+    }
+
+    if !generated_subpackages.is_empty() {
+        let subpackages = pkgfile
+            .entry("subpackage")
+            .or_insert_with(|| toml::Value::Array(vec![]))
+            .as_array_mut()
+            .expect("subpackage is not an array");
+        for subpackage in generated_subpackages {
+            subpackages
+                .push(toml::Value::try_from(subpackage).expect("Unable to serialize subpackage"));
+        }
+    }
+
+    toml::to_string(&pkgfile).expect("Unable to serialize package metadata")
+}
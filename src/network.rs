@@ -0,0 +1,296 @@
+use crate::{util, PkgBuilderError, PkgFileBuild};
+use std::{fs, os::unix::process::CommandExt, process::Command};
+
+const IP_FORWARD: &str = "/proc/sys/net/ipv4/ip_forward";
+
+// Network access of the build scripts, set with build.network_policy:
+// full (default) doesn't restrict anything.
+// none and loopback-only run the scripts in a new network namespace,
+// with loopback-only the loopback interface is brought up in it.
+// Without root a user namespace is created as well and the scripts run as root in it.
+// allow-list connects a network namespace to the host with a veth pair and NAT
+// and only permits connections to network_allow_cidrs with iptables inside it,
+// it requires root or CAP_NET_ADMIN.
+pub struct NetworkSandbox {
+    policy: Policy,
+    // network namespace created for allow-list, removed on drop
+    namespace: Option<String>,
+    // address of the namespace masqueraded on the host
+    address: Option<String>,
+    // ip_forward of the host before it was enabled, restored on drop
+    ip_forward: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Policy {
+    Full,
+    None,
+    LoopbackOnly,
+    AllowList,
+}
+
+impl NetworkSandbox {
+    pub fn setup(build: &PkgFileBuild) -> NetworkSandbox {
+        let policy = match build.network_policy.as_deref() {
+            None | Some("full") => Policy::Full,
+            Some("none") => Policy::None,
+            Some("loopback-only") => Policy::LoopbackOnly,
+            Some("allow-list") => Policy::AllowList,
+            Some(policy) => panic!(
+                "{}",
+                PkgBuilderError::UnknownNetworkPolicy {
+                    policy: policy.to_string()
+                }
+            ),
+        };
+
+        if build.network_allow_cidrs.is_some() && policy != Policy::AllowList {
+            eprintln!(
+                "Warning: network_allow_cidrs is only used with network_policy = \"allow-list\""
+            );
+        }
+
+        let mut sandbox = NetworkSandbox {
+            policy,
+            namespace: None,
+            address: None,
+            ip_forward: None,
+        };
+
+        match policy {
+            Policy::Full => {}
+            Policy::None | Policy::LoopbackOnly => require_tool("unshare"),
+            Policy::AllowList => {
+                if !util::is_root() {
+                    panic!(
+                        "{}",
+                        PkgBuilderError::NetworkPolicyRequiresRoot {
+                            policy: "allow-list".to_string()
+                        }
+                    );
+                }
+                require_tool("ip");
+                require_tool("iptables");
+                sandbox.setup_allow_list(build.network_allow_cidrs.as_deref().unwrap_or_default());
+            }
+        }
+
+        sandbox
+    }
+
+    // creates the namespace, connects it to the host and installs the firewall
+    fn setup_allow_list(&mut self, cidrs: &[String]) {
+        let id = std::process::id();
+        let namespace = format!("pkgbuilder-{}", id);
+        let host_interface = format!("pbh{}", id);
+        let namespace_interface = format!("pbn{}", id);
+        // a /30 per build, derived from the pid so parallel builds don't collide
+        let subnet = format!("10.213.{}", (id >> 6) & 255);
+        let host_address = format!("{}.{}", subnet, (id & 63) * 4 + 1);
+        let address = format!("{}.{}", subnet, (id & 63) * 4 + 2);
+
+        println!("Creating network namespace {}", namespace);
+        self.namespace = Some(namespace.clone());
+
+        run(&["ip", "netns", "add", &namespace]);
+        run(&[
+            "ip",
+            "link",
+            "add",
+            &host_interface,
+            "type",
+            "veth",
+            "peer",
+            "name",
+            &namespace_interface,
+        ]);
+        run(&[
+            "ip",
+            "link",
+            "set",
+            &namespace_interface,
+            "netns",
+            &namespace,
+        ]);
+        run(&[
+            "ip",
+            "addr",
+            "add",
+            &format!("{}/30", host_address),
+            "dev",
+            &host_interface,
+        ]);
+        run(&["ip", "link", "set", &host_interface, "up"]);
+
+        let inside = |args: &[&str]| {
+            let mut command = vec!["ip", "netns", "exec", &namespace];
+            command.extend_from_slice(args);
+            run(&command);
+        };
+        inside(&["ip", "link", "set", "lo", "up"]);
+        inside(&[
+            "ip",
+            "addr",
+            "add",
+            &format!("{}/30", address),
+            "dev",
+            &namespace_interface,
+        ]);
+        inside(&["ip", "link", "set", &namespace_interface, "up"]);
+        inside(&["ip", "route", "add", "default", "via", &host_address]);
+
+        // everything but loopback and the allowed networks is dropped
+        inside(&["iptables", "-P", "OUTPUT", "DROP"]);
+        inside(&["iptables", "-A", "OUTPUT", "-o", "lo", "-j", "ACCEPT"]);
+        inside(&[
+            "iptables",
+            "-A",
+            "OUTPUT",
+            "-m",
+            "state",
+            "--state",
+            "ESTABLISHED,RELATED",
+            "-j",
+            "ACCEPT",
+        ]);
+        for cidr in cidrs {
+            println!("Allowing connections to {}", cidr);
+            inside(&["iptables", "-A", "OUTPUT", "-d", cidr, "-j", "ACCEPT"]);
+        }
+
+        // the host forwards and masquerades the traffic of the namespace
+        let ip_forward = fs::read_to_string(IP_FORWARD).expect("Unable to read ip_forward");
+        if ip_forward.trim() != "1" {
+            fs::write(IP_FORWARD, "1").expect("Unable to enable IP forwarding");
+            self.ip_forward = Some(ip_forward);
+        }
+        run(&[
+            "iptables",
+            "-t",
+            "nat",
+            "-A",
+            "POSTROUTING",
+            "-s",
+            &format!("{}/32", address),
+            "-j",
+            "MASQUERADE",
+        ]);
+        self.address = Some(address);
+    }
+
+    // the command a script is started with, running program under the policy.
+    // user is the uid and gid the script runs as
+    pub fn command(&self, program: &str, user: Option<(u32, u32)>) -> Command {
+        let mut command = match (self.policy, &self.namespace) {
+            (Policy::AllowList, Some(namespace)) => {
+                let mut command = Command::new("ip");
+                command.arg("netns").arg("exec").arg(namespace);
+                // ip netns exec needs root, the user is switched inside the namespace
+                if let Some((uid, gid)) = user {
+                    command
+                        .arg("setpriv")
+                        .arg(format!("--reuid={}", uid))
+                        .arg(format!("--regid={}", gid))
+                        .arg("--clear-groups")
+                        .arg("--");
+                }
+                command.arg(program);
+                return command;
+            }
+            (Policy::None | Policy::LoopbackOnly, _) => {
+                let mut command = Command::new("unshare");
+                if user.is_some() || !util::is_root() {
+                    command.arg("--user").arg("--map-root-user");
+                }
+                command.arg("--net").arg("--").arg(program);
+                command
+            }
+            _ => Command::new(program),
+        };
+
+        if let Some((uid, gid)) = user {
+            command.uid(uid).gid(gid);
+        }
+        command
+    }
+
+    // run at the start of every script
+    pub fn preamble(&self) -> &'static str {
+        match self.policy {
+            Policy::LoopbackOnly => "ip link set lo up\n",
+            _ => "",
+        }
+    }
+}
+
+impl Drop for NetworkSandbox {
+    fn drop(&mut self) {
+        if let Some(ref address) = self.address {
+            try_run(&[
+                "iptables",
+                "-t",
+                "nat",
+                "-D",
+                "POSTROUTING",
+                "-s",
+                &format!("{}/32", address),
+                "-j",
+                "MASQUERADE",
+            ]);
+        }
+        if let Some(ref ip_forward) = self.ip_forward {
+            if let Err(e) = fs::write(IP_FORWARD, ip_forward) {
+                eprintln!("Warning: unable to restore ip_forward: {}", e);
+            }
+        }
+        // the veth pair is removed with the namespace
+        if let Some(ref namespace) = self.namespace {
+            println!("Removing network namespace {}", namespace);
+            try_run(&["ip", "netns", "delete", namespace]);
+        }
+    }
+}
+
+fn require_tool(tool: &str) {
+    if !util::command_exists(tool) {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: tool.to_string()
+            }
+        );
+    }
+}
+
+// runs a setup command, a failure leaves the network unrestricted
+// and aborts the build
+fn run(args: &[&str]) {
+    let output = Command::new(args[0])
+        .args(&args[1..])
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        panic!(
+            "{} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+// runs a cleanup command, failures are only reported
+fn try_run(args: &[&str]) {
+    let output = Command::new(args[0])
+        .args(&args[1..])
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        eprintln!(
+            "Warning: {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{build_env, cli, create_tarball, manifest, write_manifest};
+use std::{fs, process::Command};
+
+// pkgbuilder pack <DIR> <OUTPUT_TARBALL> --name <NAME> --version <VERSION>
+//                 [--description <DESC>] [--license <SPDX>]
+//
+// Wraps a pre-built directory tree into a package tarball,
+// the inverse of extract. The directory itself is not modified,
+// package.toml and FILES are added to a copy of it.
+pub fn run(mut args: Vec<String>) {
+    let name = cli::take_option(&mut args, "--name").expect("No package name provided");
+    let version = cli::take_option(&mut args, "--version").expect("No package version provided");
+    let description = cli::take_option(&mut args, "--description").unwrap_or_else(|| name.clone());
+    // SPDX for a license that wasn't determined
+    let license =
+        cli::take_option(&mut args, "--license").unwrap_or_else(|| "NOASSERTION".to_string());
+    let dir = args.first().expect("No directory provided");
+    let tarball = args.get(1).expect("No output tarball provided");
+
+    let staging_dir = format!(
+        "/tmp/pkgbuilder/pack_{}_{}_{}",
+        name,
+        version,
+        chrono::Utc::now().timestamp()
+    );
+    fs::create_dir_all(&staging_dir).expect("Unable to create staging directory");
+
+    let output = Command::new("cp")
+        .arg("-a")
+        .arg(format!("{}/.", dir.trim_end_matches('/')))
+        .arg(&staging_dir)
+        .output()
+        .expect("Failed to execute command");
+    if !output.status.success() {
+        fs::remove_dir_all(&staging_dir).expect("Unable to remove staging directory");
+        panic!(
+            "Unable to copy {}: {}",
+            dir,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut package = toml::Table::new();
+    package.insert("name".to_string(), toml::Value::String(name.clone()));
+    package.insert("version".to_string(), toml::Value::String(version.clone()));
+    package.insert("description".to_string(), toml::Value::String(description));
+    package.insert("license".to_string(), toml::Value::String(license));
+    package.insert(
+        "build_date".to_string(),
+        toml::Value::String(build_env::iso_8601(chrono::Utc::now().timestamp() as u64)),
+    );
+    let mut metadata = toml::Table::new();
+    metadata.insert("package".to_string(), toml::Value::Table(package));
+
+    fs::write(
+        format!("{}/package.toml", staging_dir),
+        toml::to_string(&metadata).expect("Unable to serialize package metadata"),
+    )
+    .expect("Unable to write package metadata");
+    let manifest_package = manifest::ManifestPackage {
+        name: name.clone(),
+        version,
+        pkgrel: 1,
+    };
+    write_manifest(&staging_dir, &manifest_package, &[]);
+
+    let created = create_tarball(&staging_dir, tarball, None, None);
+    fs::remove_dir_all(&staging_dir).expect("Unable to remove staging directory");
+
+    if created {
+        println!("Packed {} into {}", dir, tarball);
+    } else {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,749 @@
+use crate::{
+    expand_file_selector, util, PkgBuilderError, PkgFileBuild, PkgFilePackage, PkgFileSplitRule,
+    PkgFileSubPackage,
+};
+use std::{fs, path::Path, process::Command};
+
+// Post-processing steps run on the out directory
+// after the build script and before packaging.
+
+// removes libtool archives, unless they match one of the keep patterns
+pub fn strip_la_files(build: &PkgFileBuild, out_dir: &str) {
+    if !build.strip_la_files.unwrap_or(true) {
+        return;
+    }
+
+    let keep = build.keep_la_files.as_deref().unwrap_or_default();
+    let mut removed = 0;
+
+    for file in util::walk_files(Path::new(out_dir)) {
+        let relative = file.to_string_lossy().replace(out_dir, "");
+        if !relative.ends_with(".la") {
+            continue;
+        }
+
+        if keep
+            .iter()
+            .any(|pattern| util::glob_match(pattern, &relative))
+        {
+            println!("Keeping libtool archive: {}", relative);
+            continue;
+        }
+
+        fs::remove_file(&file).expect("Unable to remove libtool archive");
+        removed += 1;
+    }
+
+    if removed > 0 {
+        println!("Removed {} libtool archive(s)", removed);
+    }
+}
+
+const DOC_DIRS: [&str; 3] = ["/usr/share/doc", "/usr/share/info", "/usr/share/gtk-doc"];
+
+// removes documentation directories when install_docs is disabled
+pub fn remove_docs(build: &PkgFileBuild, subpackages: Option<&[PkgFileSubPackage]>, out_dir: &str) {
+    if build.install_docs.unwrap_or(true) {
+        return;
+    }
+
+    for subpackage in subpackages.unwrap_or_default() {
+        for file_selector in &subpackage.files {
+            if DOC_DIRS.iter().any(|dir| file_selector.starts_with(dir)) {
+                eprintln!(
+                    "Warning: install_docs is disabled, file selector {} of subpackage {} will match nothing",
+                    file_selector, subpackage.name
+                );
+            }
+        }
+    }
+
+    for dir in DOC_DIRS {
+        let path = format!("{}{}", out_dir, dir);
+        if !Path::new(&path).exists() {
+            continue;
+        }
+
+        let files = util::walk_files(Path::new(&path)).len();
+        fs::remove_dir_all(&path).expect("Unable to remove documentation directory");
+        println!("Removed {} ({} files)", dir, files);
+    }
+}
+
+// directories containing one entry per locale,
+// the bool tells whether plain files are locale entries as well
+const LOCALE_DIRS: [(&str, bool); 4] = [
+    ("/usr/share/locale", false),
+    ("/usr/share/i18n/locales", true),
+    ("/usr/share/X11/locale", false),
+    ("/usr/share/nls", false),
+];
+
+// removes all locales that are not listed in locale_filter
+pub fn filter_locales(build: &PkgFileBuild, out_dir: &str) {
+    let Some(ref locale_filter) = build.locale_filter else {
+        return;
+    };
+
+    let mut removed = 0;
+    let mut saved = 0;
+
+    for (dir, include_files) in LOCALE_DIRS {
+        let Ok(entries) = fs::read_dir(format!("{}{}", out_dir, dir)) else {
+            continue;
+        };
+
+        for entry in entries {
+            let path = entry.expect("Unable to read directory entry").path();
+            if !path.is_dir() && !include_files {
+                continue;
+            }
+
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            if locale_filter
+                .iter()
+                .any(|locale| locale_matches(locale, &name))
+            {
+                continue;
+            }
+
+            saved += util::disk_usage(&path);
+            if path.is_dir() {
+                fs::remove_dir_all(&path).expect("Unable to remove locale directory");
+            } else {
+                fs::remove_file(&path).expect("Unable to remove locale file");
+            }
+            removed += 1;
+        }
+    }
+
+    println!("Removed {} locale(s), saved {} KiB", removed, saved / 1024);
+}
+
+// en_US matches en_US, en_US.UTF-8 and en_US@euro
+fn locale_matches(locale: &str, name: &str) -> bool {
+    match name.strip_prefix(locale) {
+        Some(rest) => rest.is_empty() || rest.starts_with('.') || rest.starts_with('@'),
+        None => false,
+    }
+}
+
+// removes files matching the patterns of an ignore file from the out directory.
+// the format follows .gitignore: one glob per line, # starts a comment,
+// patterns without a / match file names anywhere, a trailing / matches directories
+pub fn apply_ignore_file(ignore_file: &str, out_dir: &str) {
+    let Ok(contents) = fs::read_to_string(ignore_file) else {
+        return;
+    };
+
+    let patterns = contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|pattern| {
+            let mut pattern = match pattern.strip_suffix('/') {
+                Some(dir) if dir.contains('/') => format!("{}/**", dir),
+                Some(dir) => format!("**/{}/**", dir),
+                None => pattern.to_string(),
+            };
+            if pattern.contains('/') && !pattern.starts_with('/') {
+                pattern.insert(0, '/');
+            }
+            pattern
+        })
+        .collect::<Vec<_>>();
+
+    println!("Applying ignore file: {}", ignore_file);
+
+    for file in util::walk_files(Path::new(out_dir)) {
+        let relative = file.to_string_lossy().replace(out_dir, "");
+        if patterns
+            .iter()
+            .any(|pattern| util::glob_match(pattern, &relative))
+        {
+            println!("Ignoring file: {}", relative);
+            fs::remove_file(&file).expect("Unable to remove ignored file");
+        }
+    }
+}
+
+// expands split_files_by_pattern and split_locale_packages into subpackages,
+// rules that match no files are skipped
+pub fn split_subpackages(
+    build: &PkgFileBuild,
+    package_name: &str,
+    out_dir: &str,
+) -> Vec<PkgFileSubPackage> {
+    let mut rules = build.split_files_by_pattern.clone().unwrap_or_default();
+    if build.split_locale_packages.unwrap_or(false) {
+        rules.extend(locale_rules(out_dir));
+    }
+
+    rules
+        .into_iter()
+        .filter(|rule| {
+            // selectors without wildcards are returned even if the path doesn't exist
+            let matched = rule.files.iter().any(|selector| {
+                expand_file_selector(out_dir, selector).iter().any(|path| {
+                    Path::new(&format!("{}{}", out_dir, path))
+                        .symlink_metadata()
+                        .is_ok()
+                })
+            });
+            if !matched {
+                println!(
+                    "No files for {}-{}, not creating it",
+                    package_name, rule.name_suffix
+                );
+            }
+            matched
+        })
+        .map(|rule| PkgFileSubPackage {
+            name: format!(
+                "{}-{}",
+                package_name,
+                rule.name_suffix.trim_start_matches('-')
+            ),
+            description: rule.description_template.replace("{name}", package_name),
+            files: rule.files,
+            depends: Some(
+                rule.depends
+                    .unwrap_or_else(|| vec![package_name.to_string()]),
+            ),
+            install_size_limit_mb: None,
+            install_size_minimum_kb: None,
+            provides: None,
+        })
+        .collect()
+}
+
+// a locale-<lang> rule for every locale directory
+fn locale_rules(out_dir: &str) -> Vec<PkgFileSplitRule> {
+    let Ok(entries) = fs::read_dir(format!("{}/usr/share/locale", out_dir)) else {
+        return vec![];
+    };
+
+    let mut languages = entries
+        .map(|entry| entry.expect("Unable to read directory entry").path())
+        .filter(|path| path.is_dir())
+        .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    languages.sort();
+
+    languages
+        .into_iter()
+        .map(|language| PkgFileSplitRule {
+            name_suffix: format!("locale-{}", language),
+            description_template: format!("{{name}} locale data for {}", language),
+            files: vec![format!("/usr/share/locale/{}", language)],
+            depends: None,
+        })
+        .collect()
+}
+
+const MB: u64 = 1024 * 1024;
+
+// enforces max_output_size_mb and max_single_file_size_mb
+pub fn check_output_size(build: &PkgFileBuild, out_dir: &str) -> Result<(), PkgBuilderError> {
+    if let Some(limit_mb) = build.max_single_file_size_mb {
+        for file in util::walk_files(Path::new(out_dir)) {
+            let size = file
+                .symlink_metadata()
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            if size > limit_mb.saturating_mul(MB) {
+                return Err(PkgBuilderError::FileTooLarge {
+                    file: file.to_string_lossy().replace(out_dir, ""),
+                    actual_mb: size / MB,
+                    limit_mb,
+                });
+            }
+        }
+    }
+
+    if let Some(limit_mb) = build.max_output_size_mb {
+        let size = util::disk_usage(Path::new(out_dir));
+        if size > limit_mb.saturating_mul(MB) {
+            return Err(PkgBuilderError::OutputTooLarge {
+                actual_mb: size / MB,
+                limit_mb,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// patterns of files build scripts commonly leave behind by accident
+const TEMPFILE_PATTERNS: [&str; 8] = [
+    "*.tmp",
+    "*.log",
+    "*.pid",
+    "*.lock",
+    "*.bak",
+    "*.orig",
+    "/tmp/**",
+    "/var/tmp/**",
+];
+
+// warns about temporary files and empty files in the out directory,
+// with tempfile_autoremove the temporary files are deleted.
+// empty files are only reported, __init__.py or .keep files are empty on purpose
+pub fn cleanup_tempfiles(build: &PkgFileBuild, out_dir: &str) {
+    if !build.tempfile_cleanup.unwrap_or(false) {
+        return;
+    }
+    let autoremove = build.tempfile_autoremove.unwrap_or(false);
+
+    for file in util::walk_files(Path::new(out_dir)) {
+        let relative = file.to_string_lossy().replace(out_dir, "");
+        let temporary = TEMPFILE_PATTERNS
+            .iter()
+            .any(|pattern| util::glob_match(pattern, &relative));
+        if !temporary {
+            let empty = !file.is_symlink()
+                && file
+                    .metadata()
+                    .is_ok_and(|metadata| metadata.is_file() && metadata.len() == 0);
+            if empty {
+                eprintln!("Warning: {} is empty", relative);
+            }
+            continue;
+        }
+
+        if autoremove {
+            eprintln!("Warning: removing temporary file {}", relative);
+            fs::remove_file(&file).expect("Unable to remove temporary file");
+        } else {
+            eprintln!("Warning: {} looks like a temporary file", relative);
+        }
+    }
+}
+
+// checks that every provides entry naming a file is shipped by its package,
+// the main package or the subpackage declaring it.
+// paths (containing a /) are looked up as they are,
+// shared libraries (containing .so) by file name anywhere in the package.
+// other entries are virtual packages and not checked
+pub fn check_provides(
+    build: &PkgFileBuild,
+    package: &PkgFilePackage,
+    subpackages: &[&PkgFileSubPackage],
+    out_dir: &str,
+) -> Result<(), PkgBuilderError> {
+    if !build.check_provides.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let selected = selected_files(subpackages.iter().copied(), out_dir);
+    let files = util::walk_files(Path::new(out_dir))
+        .into_iter()
+        .map(|file| file.to_string_lossy().replacen(out_dir, "", 1))
+        .collect::<Vec<_>>();
+
+    let packages = std::iter::once((None, &package.name, &package.provides)).chain(
+        subpackages
+            .iter()
+            .enumerate()
+            .map(|(index, subpackage)| (Some(index), &subpackage.name, &subpackage.provides)),
+    );
+    for (index, package, provides) in packages {
+        for name in provides.iter().flatten() {
+            let delivered = if name.contains('/') {
+                let path = format!("/{}", name.trim_start_matches('/'));
+                Path::new(&format!("{}{}", out_dir, path))
+                    .symlink_metadata()
+                    .is_ok()
+                    && owner(&selected, &path) == index
+            } else if name.contains(".so") {
+                files.iter().any(|file| {
+                    file.rsplit('/').next() == Some(name.as_str())
+                        && owner(&selected, file) == index
+                })
+            } else {
+                continue;
+            };
+
+            if !delivered {
+                return Err(PkgBuilderError::UnfulfilledProvides {
+                    package: package.clone(),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// the paths each subpackage moves out of the out directory
+fn selected_files<'a>(
+    subpackages: impl Iterator<Item = &'a PkgFileSubPackage>,
+    out_dir: &str,
+) -> Vec<Vec<String>> {
+    subpackages
+        .map(|subpackage| {
+            subpackage
+                .files
+                .iter()
+                .flat_map(|selector| expand_file_selector(out_dir, selector))
+                .collect()
+        })
+        .collect()
+}
+
+// the index of the first subpackage moving path, None if it stays in the main package.
+// selectors can name directories, everything below them is moved as well
+fn owner(selected: &[Vec<String>], path: &str) -> Option<usize> {
+    selected.iter().position(|paths| {
+        paths
+            .iter()
+            .any(|selected| path == selected || path.starts_with(&format!("{}/", selected)))
+    })
+}
+
+// checks the RPATH and RUNPATH of every ELF file in the out directory,
+// entries pointing into the build directory break the installed files.
+// $ORIGIN relative entries and system paths like /usr/lib are fine
+pub fn check_rpath(
+    build: &PkgFileBuild,
+    build_dir: &str,
+    out_dir: &str,
+) -> Result<(), PkgBuilderError> {
+    if !build.assert_no_rpath.unwrap_or(false) {
+        return Ok(());
+    }
+
+    if !util::command_exists("readelf") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "readelf".to_string()
+            }
+        );
+    }
+
+    let whitelist = build.rpath_whitelist.as_deref().unwrap_or_default();
+    for file in util::walk_files(Path::new(out_dir)) {
+        if file.is_symlink() || !util::is_elf(&file) {
+            continue;
+        }
+
+        let output = Command::new("readelf")
+            .arg("-d")
+            .arg(&file)
+            .output()
+            .expect("Failed to execute command");
+        if !output.status.success() {
+            continue;
+        }
+
+        // 0x000000000000001d (RUNPATH)            Library runpath: [/usr/lib:$ORIGIN/../lib]
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some(rpath) = line
+                .split_once("Library rpath: [")
+                .or_else(|| line.split_once("Library runpath: ["))
+                .and_then(|(_, rest)| rest.strip_suffix(']'))
+            else {
+                continue;
+            };
+
+            for entry in rpath.split(':') {
+                let into_build = entry.contains("/tmp/pkgbuilder/") || entry.starts_with(build_dir);
+                if into_build
+                    && !whitelist
+                        .iter()
+                        .any(|pattern| util::glob_match(pattern, entry))
+                {
+                    return Err(PkgBuilderError::BadRpath {
+                        file: file.to_string_lossy().replace(out_dir, ""),
+                        rpath: entry.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// renders the pkg-config file described by generate_pkg_config
+pub fn generate_pkg_config(build: &PkgFileBuild, package: &PkgFilePackage, out_dir: &str) {
+    let Some(ref spec) = build.generate_pkg_config else {
+        return;
+    };
+
+    let file_name = format!("{}.pc", spec.name);
+    if util::walk_files(Path::new(out_dir))
+        .iter()
+        .any(|file| file.file_name().is_some_and(|name| *name == *file_name))
+    {
+        println!(
+            "{} was installed by the build, not generating it",
+            file_name
+        );
+        return;
+    }
+
+    let prefix = spec
+        .prefix
+        .as_deref()
+        .or(build.install_prefix.as_deref())
+        .unwrap_or("/usr");
+
+    let mut contents = format!(
+        "prefix={}\nexec_prefix=${{prefix}}\nlibdir=${{exec_prefix}}/lib\nincludedir=${{prefix}}/include\n\n",
+        prefix
+    );
+    contents.push_str(&format!("Name: {}\n", spec.name));
+    contents.push_str(&format!("Description: {}\n", spec.description));
+    contents.push_str(&format!(
+        "Version: {}\n",
+        spec.version.as_deref().unwrap_or(&package.version)
+    ));
+    if let Some(ref requires) = spec.requires {
+        contents.push_str(&format!("Requires: {}\n", requires.join(", ")));
+    }
+    if let Some(ref libs) = spec.libs {
+        contents.push_str(&format!("Libs: {}\n", libs));
+    }
+    if let Some(ref cflags) = spec.cflags {
+        contents.push_str(&format!("Cflags: {}\n", cflags));
+    }
+
+    let pkgconfig_dir = format!("{}/usr/lib/pkgconfig", out_dir);
+    fs::create_dir_all(&pkgconfig_dir).expect("Unable to create pkgconfig directory");
+    fs::write(format!("{}/{}", pkgconfig_dir, file_name), contents)
+        .expect("Unable to write pkg-config file");
+    println!("Generated /usr/lib/pkgconfig/{}", file_name);
+}
+
+// adds the SONAME of every shared library in the out directory to the provides
+// of the package shipping it, the first subpackage selecting the file or the main package
+pub fn auto_provides(
+    build: &PkgFileBuild,
+    package: &mut PkgFilePackage,
+    subpackages: &mut [&mut PkgFileSubPackage],
+    out_dir: &str,
+) {
+    if !build.auto_provides.unwrap_or(false) {
+        return;
+    }
+
+    if !util::command_exists("readelf") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "readelf".to_string()
+            }
+        );
+    }
+
+    let selected = selected_files(subpackages.iter().map(|subpackage| &**subpackage), out_dir);
+
+    for file in util::walk_files(Path::new(out_dir)) {
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        if file.is_symlink() || !(file_name.ends_with(".so") || file_name.contains(".so.")) {
+            continue;
+        }
+
+        let output = Command::new("readelf")
+            .arg("-d")
+            .arg(&file)
+            .output()
+            .expect("Failed to execute command");
+        if !output.status.success() {
+            continue;
+        }
+
+        // 0x000000000000000e (SONAME)             Library soname: [libfoo.so.1]
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some(soname) = line
+                .split_once("Library soname: [")
+                .and_then(|(_, rest)| rest.strip_suffix(']'))
+            else {
+                continue;
+            };
+
+            let path = file.to_string_lossy().replacen(out_dir, "", 1);
+            let (name, provides) = match owner(&selected, &path) {
+                Some(index) => (
+                    &subpackages[index].name,
+                    subpackages[index].provides.get_or_insert_with(Vec::new),
+                ),
+                None => (&package.name, package.provides.get_or_insert_with(Vec::new)),
+            };
+
+            if !provides.iter().any(|provide| provide == soname) {
+                println!("{} provides {}", name, soname);
+                provides.push(soname.to_string());
+            }
+        }
+    }
+}
+
+// removes .note.gnu.build-id from every ELF file in the out directory,
+// in case the build didn't honour -Wl,--build-id=none
+pub fn remove_build_ids(build: &PkgFileBuild, out_dir: &str) {
+    if !build.no_build_id.unwrap_or(false) {
+        return;
+    }
+
+    if !util::command_exists("objcopy") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "objcopy".to_string()
+            }
+        );
+    }
+
+    let mut stripped = 0;
+    for file in util::walk_files(Path::new(out_dir)) {
+        if file.is_symlink() || !util::is_elf(&file) {
+            continue;
+        }
+
+        let output = Command::new("objcopy")
+            .arg("--remove-section=.note.gnu.build-id")
+            .arg(&file)
+            .output()
+            .expect("Failed to execute command");
+        if output.status.success() {
+            stripped += 1;
+        } else {
+            eprintln!(
+                "Warning: unable to remove the build id from {}: {}",
+                file.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+    }
+
+    if stripped > 0 {
+        println!("Removed build ids from {} ELF file(s)", stripped);
+    }
+}
+
+// compiles every python source in the out directory into __pycache__,
+// with and without optimization
+pub fn compile_python_bytecode(build: &PkgFileBuild, out_dir: &str, source_date_epoch: u64) {
+    if !build.python_bytecode_compile.unwrap_or(false) {
+        return;
+    }
+
+    if !util::command_exists("python3") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "python3".to_string()
+            }
+        );
+    }
+
+    let sources = util::walk_files(Path::new(out_dir))
+        .into_iter()
+        .filter(|file| !file.is_symlink())
+        .filter(|file| file.extension().is_some_and(|extension| extension == "py"))
+        .collect::<Vec<_>>();
+
+    for optimize in [false, true] {
+        for chunk in sources.chunks(512) {
+            let mut command = Command::new("python3");
+            if optimize {
+                command.arg("-O");
+            }
+
+            // SOURCE_DATE_EPOCH makes py_compile use hash based .pyc files
+            let output = command
+                .arg("-m")
+                .arg("py_compile")
+                .args(chunk)
+                .env("SOURCE_DATE_EPOCH", source_date_epoch.to_string())
+                .output()
+                .expect("Failed to execute command");
+            if !output.status.success() {
+                eprintln!(
+                    "Warning: unable to compile python bytecode: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+        }
+    }
+
+    if !sources.is_empty() {
+        println!("Compiled bytecode for {} python file(s)", sources.len());
+    }
+}
+
+// the __pycache__ files compiled from a python source,
+// paths are relative to the out directory
+pub fn bytecode_files(out_dir: &str, source: &str) -> Vec<String> {
+    let Some((dir, file_name)) = source.rsplit_once('/') else {
+        return vec![];
+    };
+    let Some(stem) = file_name.strip_suffix(".py") else {
+        return vec![];
+    };
+
+    let Ok(entries) = fs::read_dir(format!("{}{}/__pycache__", out_dir, dir)) else {
+        return vec![];
+    };
+
+    let mut files = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(&format!("{}.", stem)) && name.ends_with(".pyc"))
+        .map(|name| format!("{}/__pycache__/{}", dir, name))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn has_build_id(file: &Path) -> bool {
+        let output = Command::new("readelf")
+            .arg("-S")
+            .arg("-W")
+            .arg(file)
+            .output()
+            .expect("Failed to execute command");
+        String::from_utf8_lossy(&output.stdout).contains(".note.gnu.build-id")
+    }
+
+    #[test]
+    fn remove_build_ids_strips_the_note() {
+        if !["cc", "objcopy", "readelf"]
+            .iter()
+            .all(|tool| util::command_exists(tool))
+        {
+            eprintln!("Skipping, cc, objcopy or readelf is not installed");
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("pkgbuilder-build-id-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.c");
+        let binary = dir.join("main");
+        fs::write(&source, "int main(void) { return 0; }\n").unwrap();
+        let status = Command::new("cc")
+            .arg("-Wl,--build-id")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .expect("Failed to execute command");
+        assert!(status.success());
+        assert!(has_build_id(&binary));
+
+        let build: PkgFileBuild = toml::from_str("script = \"\"\nno_build_id = true").unwrap();
+        remove_build_ids(&build, dir.to_str().unwrap());
+
+        let stripped = has_build_id(&binary);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!stripped);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{config::GlobalConfig, PkgBuilderError};
+
+// profile used when neither --profile nor the global config selects one
+const DEFAULT_PROFILE: &str = "release";
+
+// Merges [profile.<name>] over the [build] section of a PkgFile
+// and removes all profile sections.
+// A profile selected with --profile has to exist, the default from the
+// global config and the implicit release profile may be left out.
+pub fn apply(pkgfile: &mut toml::Table, profile: Option<&str>) -> Result<(), PkgBuilderError> {
+    let (name, explicit) = match profile {
+        Some(name) => (name.to_string(), true),
+        None => match GlobalConfig::load()
+            .profile
+            .and_then(|profile| profile.default)
+        {
+            Some(name) => (name, false),
+            None => (DEFAULT_PROFILE.to_string(), false),
+        },
+    };
+
+    let mut profiles = match pkgfile.remove("profile") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        _ => toml::Table::new(),
+    };
+
+    let Some(toml::Value::Table(overrides)) = profiles.remove(&name) else {
+        if explicit {
+            return Err(PkgBuilderError::UnknownProfile { name });
+        }
+        return Ok(());
+    };
+
+    let build = pkgfile
+        .entry("build")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    if let toml::Value::Table(build) = build {
+        merge(build, overrides);
+    }
+
+    Ok(())
+}
+
+// tables are merged key by key, any other value replaces the base value
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PkgFile;
+
+    fn pkgfile(contents: &str) -> toml::Table {
+        toml::from_str(contents).expect("Invalid test PkgFile")
+    }
+
+    // the [package] section every PkgFile needs
+    const PACKAGE: &str = r#"
+        [package]
+        name = "foo"
+        version = "1.0"
+        description = "Foo"
+        license = "MIT"
+    "#;
+
+    #[test]
+    fn profile_overrides_build_fields() {
+        let mut table = pkgfile(&format!(
+            r#"
+            {}
+            [build]
+            script = "make"
+            strip_la_files = true
+            cmake_flags = {{ CMAKE_BUILD_TYPE = "Release", BUILD_TESTING = "OFF" }}
+
+            [profile.debug]
+            sanitizer = "address"
+            strip_la_files = false
+            cmake_flags = {{ CMAKE_BUILD_TYPE = "Debug" }}
+            "#,
+            PACKAGE
+        ));
+
+        apply(&mut table, Some("debug")).unwrap();
+        assert!(!table.contains_key("profile"));
+
+        let pkgfile: PkgFile = table.try_into().unwrap();
+        let build = pkgfile.build.unwrap();
+        assert_eq!(build.script, "make");
+        assert_eq!(build.sanitizer.as_deref(), Some("address"));
+        assert_eq!(build.strip_la_files, Some(false));
+        let cmake_flags = build.cmake_flags.unwrap();
+        assert_eq!(cmake_flags["CMAKE_BUILD_TYPE"], "Debug");
+        // keys the profile doesn't set are kept
+        assert_eq!(cmake_flags["BUILD_TESTING"], "OFF");
+    }
+
+    #[test]
+    fn profile_without_build_section_creates_it() {
+        let mut table = pkgfile(&format!(
+            r#"
+            {}
+            [profile.debug]
+            script = "make debug"
+            sanitizer = "undefined"
+            "#,
+            PACKAGE
+        ));
+
+        apply(&mut table, Some("debug")).unwrap();
+
+        let pkgfile: PkgFile = table.try_into().unwrap();
+        let build = pkgfile.build.unwrap();
+        assert_eq!(build.script, "make debug");
+        assert_eq!(build.sanitizer.as_deref(), Some("undefined"));
+    }
+
+    #[test]
+    fn unknown_selected_profile_fails() {
+        let mut table = pkgfile(&format!(
+            r#"
+            {}
+            [build]
+            script = "make"
+            "#,
+            PACKAGE
+        ));
+
+        assert!(matches!(
+            apply(&mut table, Some("debug")),
+            Err(PkgBuilderError::UnknownProfile { name }) if name == "debug"
+        ));
+    }
+
+    #[test]
+    fn merge_replaces_values_and_merges_tables() {
+        let mut base = pkgfile(
+            r#"
+            hermetic_extra_paths = ["/opt/a"]
+            cmake_flags = { A = "1", B = "2" }
+            "#,
+        );
+        let overrides = pkgfile(
+            r#"
+            hermetic_extra_paths = ["/opt/b"]
+            cmake_flags = { B = "3" }
+            "#,
+        );
+
+        merge(&mut base, overrides);
+
+        assert_eq!(base["hermetic_extra_paths"].as_array().unwrap().len(), 1);
+        assert_eq!(base["hermetic_extra_paths"][0].as_str(), Some("/opt/b"));
+        assert_eq!(base["cmake_flags"]["A"].as_str(), Some("1"));
+        assert_eq!(base["cmake_flags"]["B"].as_str(), Some("3"));
+    }
+}
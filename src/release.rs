@@ -0,0 +1,148 @@
+use crate::{util, PkgFileSource, Secret};
+use serde_json::Value;
+use std::{
+    env,
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+// Sources downloaded from a GitHub or GitLab release.
+// The release is looked up through the API and source is replaced with the
+// download url of the first asset matching asset_pattern.
+// GITHUB_TOKEN and GITLAB_TOKEN are used for private repositories.
+
+// replaces the source url of a release source with the url of its asset
+pub fn resolve(source: &mut PkgFileSource) {
+    if let Some(ref release) = source.github_release {
+        let api = match release.host {
+            Some(ref host) => format!("https://{}/api/v3", host),
+            None => "https://api.github.com".to_string(),
+        };
+        let mut headers = vec!["Accept: application/vnd.github+json".to_string()];
+        if let Ok(token) = env::var("GITHUB_TOKEN") {
+            headers.push(format!("Authorization: Bearer {}", token));
+        }
+
+        let url = format!(
+            "{}/repos/{}/{}/releases/tags/{}",
+            api, release.owner, release.repo, release.tag
+        );
+        let release_json = api_request(&url, &headers);
+        let asset = release_json["assets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|asset| {
+                asset["name"]
+                    .as_str()
+                    .is_some_and(|name| util::glob_match(&release.asset_pattern, name))
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "No asset of {}/{} {} matches {}",
+                    release.owner, release.repo, release.tag, release.asset_pattern
+                )
+            });
+
+        source.source = asset["browser_download_url"]
+            .as_str()
+            .expect("Release asset has no download url")
+            .to_string();
+
+        // assets of private repositories can only be downloaded through the API
+        if env::var("GITHUB_TOKEN").is_ok() {
+            let api_url = asset["url"].as_str().expect("Release asset has no API url");
+            headers[0] = "Accept: application/octet-stream".to_string();
+            source.api_download = Some((
+                api_url.to_string(),
+                headers.into_iter().map(Secret).collect(),
+            ));
+        }
+    } else if let Some(ref release) = source.gitlab_release {
+        let host = release.host.as_deref().unwrap_or("gitlab.com");
+        let mut headers = Vec::new();
+        if let Ok(token) = env::var("GITLAB_TOKEN") {
+            headers.push(format!("PRIVATE-TOKEN: {}", token));
+        }
+
+        let url = format!(
+            "https://{}/api/v4/projects/{}/releases/{}",
+            host,
+            release.project.replace('/', "%2F"),
+            release.tag
+        );
+        let release_json = api_request(&url, &headers);
+        let link = release_json["assets"]["links"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|link| {
+                link["name"]
+                    .as_str()
+                    .is_some_and(|name| util::glob_match(&release.asset_pattern, name))
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "No asset of {} {} matches {}",
+                    release.project, release.tag, release.asset_pattern
+                )
+            });
+
+        source.source = link["direct_asset_url"]
+            .as_str()
+            .or(link["url"].as_str())
+            .expect("Release asset has no download url")
+            .to_string();
+
+        if !headers.is_empty() {
+            source.api_download = Some((
+                source.source.clone(),
+                headers.into_iter().map(Secret).collect(),
+            ));
+        }
+    } else {
+        return;
+    }
+
+    println!("Using release asset {}", source.source);
+}
+
+fn api_request(url: &str, headers: &[String]) -> Value {
+    println!("Looking up release {}", url);
+    let output = curl(&["-fsSL", url], headers);
+    if !output.status.success() {
+        panic!(
+            "Release lookup failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    serde_json::from_slice(&output.stdout).expect("Unable to parse the release")
+}
+
+// runs curl with the headers passed on stdin,
+// so tokens don't show up in the process list
+pub fn curl(args: &[&str], headers: &[String]) -> Output {
+    let mut child = Command::new("curl")
+        .arg("--config")
+        .arg("-")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+
+    let config = headers
+        .iter()
+        .map(|header| format!("header = \"{}\"\n", header.replace('"', "\\\"")))
+        .collect::<String>();
+    child
+        .stdin
+        .take()
+        .expect("Failed to open stdin")
+        .write_all(config.as_bytes())
+        .expect("Failed to write to stdin");
+
+    child.wait_with_output().expect("Failed to wait on child")
+}
@@ -0,0 +1,229 @@
+use crate::{archive, manifest, util, PkgFile};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fs, path::PathBuf};
+
+// The PACKAGES index at the root of a package repository.
+// It is a TOML file with one [[package]] entry per tarball in the repository.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RepoIndex {
+    #[serde(default)]
+    pub package: Vec<RepoPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RepoPackage {
+    pub name: String,
+    pub version: String,
+    pub pkgrel: Option<u32>,
+    // tarball file name relative to the repository directory
+    pub filename: String,
+    // sha256 of the tarball
+    pub sha256: Option<String>,
+    // names of the packages this package depends on
+    pub depends: Option<Vec<String>>,
+}
+
+pub fn read_index(repo_dir: &str) -> RepoIndex {
+    let index_path = format!("{}/PACKAGES", repo_dir);
+    let contents = fs::read_to_string(&index_path)
+        .unwrap_or_else(|_| panic!("Unable to read the package index: {}", index_path));
+
+    toml::from_str(&contents).expect("Unable to parse the package index")
+}
+
+pub fn write_index(repo_dir: &str, index: &RepoIndex) {
+    let contents = toml::to_string(index).expect("Unable to serialize the package index");
+    fs::write(format!("{}/PACKAGES", repo_dir), contents)
+        .expect("Unable to write the package index");
+}
+
+// The REPO.toml index at the root of a repository that is split
+// into one subdirectory per architecture.
+#[derive(Debug, Serialize)]
+struct RepoArchIndex {
+    arch: Vec<RepoArch>,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoArch {
+    name: String,
+    // subdirectory relative to the repository root
+    path: String,
+}
+
+// writes REPO.toml listing every subdirectory containing a PACKAGES index
+pub fn write_repo_toml(repo_dir: &str) {
+    let mut arches = fs::read_dir(repo_dir)
+        .expect("Unable to read repository directory")
+        .map(|entry| entry.expect("Unable to read directory entry").path())
+        .filter(|path| path.join("PACKAGES").is_file())
+        .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    arches.sort();
+
+    let index = RepoArchIndex {
+        arch: arches
+            .into_iter()
+            .map(|arch| RepoArch {
+                path: arch.clone(),
+                name: arch,
+            })
+            .collect(),
+    };
+
+    let contents = toml::to_string(&index).expect("Unable to serialize REPO.toml");
+    fs::write(format!("{}/REPO.toml", repo_dir), contents).expect("Unable to write REPO.toml");
+}
+
+// file names of all package tarballs in a repository directory
+pub fn list_tarballs(repo_dir: &str) -> Vec<String> {
+    let mut tarballs = fs::read_dir(repo_dir)
+        .expect("Unable to read repository directory")
+        .map(|entry| entry.expect("Unable to read directory entry").file_name())
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .filter(|file_name| package_name(file_name).is_some())
+        .collect::<Vec<_>>();
+    tarballs.sort();
+    tarballs
+}
+
+// package tarballs are named <package name>.tar.<compression>
+pub fn package_name(tarball: &str) -> Option<&str> {
+    tarball
+        .rsplit_once(".tar.")
+        .map(|(name, _)| name)
+        .filter(|name| !name.is_empty())
+}
+
+// reads the package.toml embedded in a tarball
+pub fn read_package_metadata(tarball: &str) -> Option<PkgFile> {
+    let metadata = archive::read_entry(tarball, "./package.toml")?;
+    toml::from_str(&String::from_utf8_lossy(&metadata)).ok()
+}
+
+// the package line of the FILES manifest embedded in a tarball
+pub fn read_manifest_package(tarball: &str) -> Option<manifest::ManifestPackage> {
+    let files = archive::read_entry(tarball, "./FILES")?;
+    manifest::parse_package(&String::from_utf8_lossy(&files))
+}
+
+// builds the index from the tarballs in a repository directory
+pub fn generate_index(repo_dir: &str) -> RepoIndex {
+    let mut index = RepoIndex::default();
+
+    for tarball in list_tarballs(repo_dir) {
+        let path = format!("{}/{}", repo_dir, tarball);
+        let name = package_name(&tarball).unwrap_or_default().to_string();
+        let Some(pkgfile) = read_package_metadata(&path) else {
+            // built with output.include_pkgfile = false
+            match read_manifest_package(&path) {
+                Some(package) => index.package.push(RepoPackage {
+                    name,
+                    depends: None,
+                    version: package.version,
+                    pkgrel: Some(package.pkgrel),
+                    sha256: util::sha256_files(&[PathBuf::from(&path)]).pop(),
+                    filename: tarball,
+                }),
+                None => eprintln!(
+                    "Warning: {} has neither a readable package.toml nor a FILES manifest naming the package, skipping",
+                    tarball
+                ),
+            }
+            continue;
+        };
+
+        // subpackages share the package.toml of their main package
+        let depends = if name == pkgfile.package.name {
+            pkgfile.package.depends
+        } else {
+            pkgfile
+                .subpackage
+                .into_iter()
+                .flatten()
+                .find(|subpackage| subpackage.name == name)
+                .and_then(|subpackage| subpackage.depends)
+        };
+
+        index.package.push(RepoPackage {
+            name,
+            depends,
+            version: pkgfile.package.version,
+            pkgrel: pkgfile.package.pkgrel,
+            sha256: util::sha256_files(&[PathBuf::from(&path)]).pop(),
+            filename: tarball,
+        });
+    }
+
+    index
+}
+
+// compares two version strings segment by segment like vercmp,
+// numeric segments are compared as numbers and are newer than alphabetic ones.
+// an extra numeric segment is newer (1.0.1 > 1.0), an extra alphabetic one
+// is a pre-release and older (1.0rc1 < 1.0)
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let a = version_segments(a);
+    let b = version_segments(b);
+
+    for (a, b) in a.iter().zip(b.iter()) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            (Ok(_), Err(_)) => Ordering::Greater,
+            (Err(_), Ok(_)) => Ordering::Less,
+            (Err(_), Err(_)) => a.cmp(b),
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    let extra_is_release = |segment: &String| segment.starts_with(|c: char| c.is_ascii_digit());
+    match (a.get(b.len()), b.get(a.len())) {
+        (Some(extra), _) if extra_is_release(extra) => Ordering::Greater,
+        (Some(_), _) => Ordering::Less,
+        (_, Some(extra)) if extra_is_release(extra) => Ordering::Less,
+        (_, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// compares version and pkgrel of two packages
+pub fn compare_releases(
+    a_version: &str,
+    a_pkgrel: Option<u32>,
+    b_version: &str,
+    b_pkgrel: Option<u32>,
+) -> Ordering {
+    compare_versions(a_version, b_version)
+        .then_with(|| a_pkgrel.unwrap_or(1).cmp(&b_pkgrel.unwrap_or(1)))
+}
+
+fn version_segments(version: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+
+    for c in version.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                segments.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        // split between digits and letters, e.g. 1.2rc1 -> 1, 2, rc, 1
+        if let Some(last) = current.chars().last() {
+            if last.is_ascii_digit() != c.is_ascii_digit() {
+                segments.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(c);
+    }
+
+    if !current.is_empty() {
+        segments.push(current);
+    }
+
+    segments
+}
@@ -0,0 +1,868 @@
+use serde_json::{json, Map, Value};
+use std::fs;
+
+const SCHEMA_ID: &str =
+    "https://github.com/stable-os/pkg-builder/releases/latest/download/pkgfile.schema.json";
+
+// pkgbuilder generate-schema
+//
+// Prints the JSON Schema of the PkgFile format.
+pub fn run_generate(_args: Vec<String>) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&pkgfile_schema()).expect("Unable to serialize the schema")
+    );
+}
+
+// pkgbuilder validate-schema <PKGFILE>
+//
+// Validates a PkgFile against the JSON Schema of the PkgFile format.
+pub fn run_validate(args: Vec<String>) {
+    let file_path = args.first().expect("No file path provided");
+
+    let contents = fs::read_to_string(file_path).expect("Unable to read the file");
+    let pkgfile: toml::Value = toml::from_str(&contents).expect("Unable to parse the TOML file");
+    let pkgfile = serde_json::to_value(pkgfile).expect("Unable to convert the PkgFile to JSON");
+
+    let mut errors = Vec::new();
+    validate(&pkgfile_schema(), &pkgfile, "", &mut errors);
+
+    if errors.is_empty() {
+        println!("{} is valid", file_path);
+        return;
+    }
+
+    for error in &errors {
+        eprintln!("Error: {}", error);
+    }
+    eprintln!("{} has {} schema error(s)", file_path, errors.len());
+    std::process::exit(1);
+}
+
+// The schema is written by hand and has to be kept in sync
+// with the PkgFile structs in main.rs.
+pub fn pkgfile_schema() -> Value {
+    let mut schema = object(
+        "PkgFile",
+        "Describes how a package is fetched, built and split into tarballs.",
+        vec![
+            ("package", package_schema()),
+            (
+                "subpackage",
+                array(
+                    "Subpackages",
+                    "Packages split off the build output.",
+                    subpackage_schema(),
+                ),
+            ),
+            (
+                "source",
+                array(
+                    "Sources",
+                    "Sources fetched into the build directory.",
+                    source_schema(),
+                ),
+            ),
+            ("build", build_schema(true)),
+            ("output", output_schema()),
+            (
+                "profile",
+                json!({
+                    "title": "Build profiles",
+                    "description": "Named sections merged over [build] when selected with --profile.",
+                    "type": "object",
+                    "additionalProperties": build_schema(false),
+                }),
+            ),
+        ],
+        &["package"],
+    );
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema["$id"] = json!(SCHEMA_ID);
+    schema
+}
+
+fn package_schema() -> Value {
+    object(
+        "Package",
+        "Name, version and relations of the package.",
+        vec![
+            ("name", string("Name", "Name of the package and its tarball.")),
+            ("version", string("Version", "Upstream version of the package.")),
+            (
+                "pkgrel",
+                integer("Release", "Release number, bumped when the PkgFile changes without a new upstream version."),
+            ),
+            ("description", string("Description", "Short description of the package.")),
+            ("license", string("License", "SPDX license expression of the package.")),
+            (
+                "pkgfile_ignore",
+                string("Ignore file", "Ignore file relative to the PkgFile, default is .pkgbuilderignore."),
+            ),
+            (
+                "source_date_epoch",
+                integer("SOURCE_DATE_EPOCH", "Unix timestamp of the build, default is the build time."),
+            ),
+            (
+                "backup",
+                strings("Backup files", "Configuration files that are not overwritten on upgrade when modified."),
+            ),
+            ("depends", strings("Dependencies", "Packages required at runtime.")),
+            ("makedepends", strings("Build dependencies", "Packages required to build the package.")),
+            ("provides", strings("Provides", "Capabilities such as shared library SONAMEs the package provides.")),
+            (
+                "source_package",
+                string("Source package", "Name of the source package this package is built from, default is name."),
+            ),
+            ("url", string("URL", "Upstream homepage of the package.")),
+        ],
+        &["name", "version", "description", "license"],
+    )
+}
+
+fn subpackage_schema() -> Value {
+    object(
+        "Subpackage",
+        "A package made of files moved out of the main package.",
+        vec![
+            (
+                "name",
+                string("Name", "Name of the subpackage and its tarball."),
+            ),
+            (
+                "description",
+                string("Description", "Short description of the subpackage."),
+            ),
+            (
+                "files",
+                strings(
+                    "Files",
+                    "Paths or glob patterns of the files moved into the subpackage.",
+                ),
+            ),
+            (
+                "depends",
+                strings("Dependencies", "Packages the subpackage depends on."),
+            ),
+            (
+                "install_size_limit_mb",
+                integer("Size limit", "Maximum size of the selected files in MB."),
+            ),
+            (
+                "install_size_minimum_kb",
+                integer("Minimum size", "Minimum size of the selected files in KB."),
+            ),
+            (
+                "provides",
+                strings(
+                    "Provides",
+                    "Capabilities such as shared library SONAMEs the subpackage provides.",
+                ),
+            ),
+        ],
+        &["name", "description", "files"],
+    )
+}
+
+fn source_schema() -> Value {
+    object(
+        "Source",
+        "A git repository, archive or other source fetched before the build.",
+        vec![
+            ("source", string("URL", "URL of the source, its type is detected from the URL.")),
+            ("git_ref", string("Git ref", "Branch or tag to clone.")),
+            ("git_commit", string("Git commit", "Commit to reset the clone to.")),
+            (
+                "destination",
+                string("Destination", "Directory relative to the build directory, default is its root."),
+            ),
+            (
+                "archive_password",
+                string("Archive password", "Password of an encrypted zip archive, $ENV{VAR} reads it from the environment."),
+            ),
+            (
+                "git_mirror_of",
+                string("Mirror of", "Canonical upstream URL when the source is a mirror."),
+            ),
+            (
+                "vcs",
+                enumeration("VCS", "Version control system of the source.", &["git", "hg", "bzr", "rsync"]),
+            ),
+            ("rsync_options", strings("rsync options", "Extra flags for rsync sources.")),
+            (
+                "extract_to_subdirectory",
+                boolean("Extract to subdirectory", "Extract archives into a directory named after the archive."),
+            ),
+            ("mirrors", strings("Mirrors", "Alternative URLs of an archive source.")),
+            (
+                "mirror_strategy",
+                enumeration("Mirror strategy", "Order the URLs are tried in.", &["sequential", "random", "fastest"]),
+            ),
+            ("git_depth", integer("Git depth", "Number of commits to clone, default is 1.")),
+            (
+                "git_shallow_since",
+                string("Git shallow since", "Clone the history since a date instead of a number of commits."),
+            ),
+            ("hg_rev", string("Mercurial revision", "Revision to check out.")),
+            ("hg_tag", string("Mercurial tag", "Tag to check out.")),
+            ("hg_branch", string("Mercurial branch", "Branch to check out.")),
+            (
+                "checksum",
+                string("Checksum", "Hex digest of the archive, the algorithm is detected from its length."),
+            ),
+            ("hash_file", string("Hash file", "URL of a checksum file to take the checksum from.")),
+            (
+                "git_bundle",
+                string("Git bundle", "Local git bundle cloned instead of the source URL."),
+            ),
+            (
+                "git_worktree",
+                boolean(
+                    "Git worktree",
+                    "Check out a worktree of a bare repository shared between builds instead of cloning.",
+                ),
+            ),
+            (
+                "github_release",
+                object(
+                    "GitHub release",
+                    "Download the asset of a GitHub release matching asset_pattern instead of the source URL.",
+                    vec![
+                        ("owner", string("Owner", "User or organization owning the repository.")),
+                        ("repo", string("Repository", "Name of the repository.")),
+                        ("tag", string("Tag", "Tag of the release.")),
+                        ("asset_pattern", string("Asset pattern", "Glob pattern matched against the asset names.")),
+                        ("host", string("Host", "GitHub Enterprise host, default is github.com.")),
+                    ],
+                    &["owner", "repo", "tag", "asset_pattern"],
+                ),
+            ),
+            (
+                "gitlab_release",
+                object(
+                    "GitLab release",
+                    "Download the asset of a GitLab release matching asset_pattern instead of the source URL.",
+                    vec![
+                        ("project", string("Project", "Path of the project including its groups.")),
+                        ("tag", string("Tag", "Tag of the release.")),
+                        ("asset_pattern", string("Asset pattern", "Glob pattern matched against the asset names.")),
+                        ("host", string("Host", "GitLab host, default is gitlab.com.")),
+                    ],
+                    &["project", "tag", "asset_pattern"],
+                ),
+            ),
+        ],
+        &["source"],
+    )
+}
+
+// the build script is optional in profiles
+fn build_schema(require_script: bool) -> Value {
+    let required: &[&str] = if require_script { &["script"] } else { &[] };
+
+    object(
+        "Build",
+        "How the package is built.",
+        vec![
+            (
+                "script",
+                string("Script", "Bash script building the package into $OUT."),
+            ),
+            (
+                "fakeroot",
+                boolean("fakeroot", "Run the build script under fakeroot."),
+            ),
+            ("distcc", boolean("distcc", "Compile with distcc.")),
+            (
+                "distcc_hosts",
+                strings("distcc hosts", "Hosts to distribute compilation to."),
+            ),
+            (
+                "distcc_jobs",
+                integer("distcc jobs", "Number of parallel distcc jobs."),
+            ),
+            (
+                "strip_la_files",
+                boolean(
+                    "Strip .la files",
+                    "Remove libtool archives, default is true.",
+                ),
+            ),
+            (
+                "keep_la_files",
+                strings(
+                    "Keep .la files",
+                    "Glob patterns of libtool archives that are kept.",
+                ),
+            ),
+            (
+                "install_docs",
+                boolean("Install docs", "Package documentation, default is true."),
+            ),
+            (
+                "locale_filter",
+                strings(
+                    "Locale filter",
+                    "Locales to keep, all are kept when not set.",
+                ),
+            ),
+            (
+                "install_prefix",
+                string(
+                    "Install prefix",
+                    "Prefix the package is installed to, default is /usr.",
+                ),
+            ),
+            (
+                "cmake_flags",
+                string_map(
+                    "CMake flags",
+                    "Passed as -D<KEY>=<VALUE> in CMAKE_EXTRA_ARGS.",
+                ),
+            ),
+            (
+                "meson_flags",
+                string_map(
+                    "Meson flags",
+                    "Passed as -D<KEY>=<VALUE> in MESON_EXTRA_ARGS.",
+                ),
+            ),
+            (
+                "pre_upgrade",
+                string(
+                    "Pre upgrade",
+                    "Script run before upgrading an installed version.",
+                ),
+            ),
+            (
+                "post_upgrade",
+                string(
+                    "Post upgrade",
+                    "Script run after upgrading an installed version.",
+                ),
+            ),
+            (
+                "split_locale_packages",
+                boolean(
+                    "Split locale packages",
+                    "Move every locale into a <name>-locale-<lang> subpackage.",
+                ),
+            ),
+            (
+                "max_output_size_mb",
+                integer(
+                    "Output size limit",
+                    "Maximum size of the build output in MB.",
+                ),
+            ),
+            (
+                "max_single_file_size_mb",
+                integer("File size limit", "Maximum size of a single file in MB."),
+            ),
+            (
+                "sccache",
+                boolean("sccache", "Cache compilation with sccache."),
+            ),
+            (
+                "sccache_bucket",
+                string("sccache bucket", "Cloud storage bucket for sccache."),
+            ),
+            (
+                "sccache_region",
+                string("sccache region", "Region of the sccache bucket."),
+            ),
+            (
+                "python_flags",
+                object(
+                    "Python flags",
+                    "Environment for python packages.",
+                    vec![
+                        (
+                            "site_packages_dir",
+                            string("Site packages", "Appended to PYTHONPATH."),
+                        ),
+                        (
+                            "write_bytecode",
+                            boolean("Write bytecode", "Sets PYTHONDONTWRITEBYTECODE when false."),
+                        ),
+                        (
+                            "pip_args",
+                            strings("pip arguments", "Appended to PIP_ARGS."),
+                        ),
+                    ],
+                    &[],
+                ),
+            ),
+            (
+                "generate_pkg_config",
+                object(
+                    "pkg-config file",
+                    "Writes usr/lib/pkgconfig/<name>.pc unless the build installed one.",
+                    vec![
+                        ("name", string("Name", "Name of the pkg-config module.")),
+                        (
+                            "description",
+                            string("Description", "Description of the module."),
+                        ),
+                        (
+                            "version",
+                            string("Version", "Default is the package version."),
+                        ),
+                        ("prefix", string("Prefix", "Default is the install prefix.")),
+                        ("libs", string("Libs", "Linker flags of the module.")),
+                        ("cflags", string("Cflags", "Compiler flags of the module.")),
+                        (
+                            "requires",
+                            strings("Requires", "Modules the module requires."),
+                        ),
+                    ],
+                    &["name", "description"],
+                ),
+            ),
+            (
+                "sanitizer",
+                enumeration(
+                    "Sanitizer",
+                    "Sanitizer to build with.",
+                    &["address", "thread", "memory", "undefined", "none"],
+                ),
+            ),
+            (
+                "coverage",
+                boolean("Coverage", "Build with coverage instrumentation."),
+            ),
+            (
+                "coverage_format",
+                enumeration(
+                    "Coverage format",
+                    "Format of the coverage report.",
+                    &["lcov", "cobertura"],
+                ),
+            ),
+            (
+                "setup_script",
+                string("Setup script", "Script run before the build script."),
+            ),
+            (
+                "setup_workdir",
+                string(
+                    "Setup directory",
+                    "Directory of the setup script relative to the build directory.",
+                ),
+            ),
+            (
+                "use_vendored_libs",
+                boolean(
+                    "Use vendored libraries",
+                    "Allow bundled copies of libraries.",
+                ),
+            ),
+            (
+                "vendored_exceptions",
+                strings(
+                    "Vendored exceptions",
+                    "Libraries that are allowed to be bundled.",
+                ),
+            ),
+            (
+                "auto_provides",
+                boolean(
+                    "Auto provides",
+                    "Add the SONAMEs of built shared libraries to provides.",
+                ),
+            ),
+            (
+                "force_user",
+                string(
+                    "Force user",
+                    "User to run the scripts as when running as root.",
+                ),
+            ),
+            (
+                "git_config_safe_directory",
+                boolean(
+                    "Git safe directory",
+                    "Trust every repository in git during the build.",
+                ),
+            ),
+            (
+                "host_env_deny_list",
+                strings(
+                    "Environment deny list",
+                    "Names or glob patterns of variables removed from the build environment.",
+                ),
+            ),
+            (
+                "no_build_id",
+                boolean(
+                    "No build ID",
+                    "Link without and strip .note.gnu.build-id sections.",
+                ),
+            ),
+            (
+                "python_bytecode_compile",
+                boolean(
+                    "Compile python bytecode",
+                    "Compile python sources into __pycache__.",
+                ),
+            ),
+            (
+                "check",
+                string(
+                    "Check script",
+                    "Script run after the build script, e.g. the test suite.",
+                ),
+            ),
+            (
+                "split_files_by_pattern",
+                array(
+                    "Split rules",
+                    "Subpackages generated from glob patterns.",
+                    object(
+                        "Split rule",
+                        "A subpackage named <package>-<name_suffix>.",
+                        vec![
+                            ("name_suffix", string("Name suffix", "Appended to the package name.")),
+                            ("description_template", string("Description", "{name} is replaced with the package name.")),
+                            ("files", strings("Files", "Paths or glob patterns of the files moved into the subpackage.")),
+                            ("depends", strings("Dependencies", "Packages the subpackage depends on, default is the main package.")),
+                        ],
+                        &["name_suffix", "description_template", "files"],
+                    ),
+                ),
+            ),
+            ("audit_deps", boolean("Audit dependencies", "Audit the dependencies of rust, node and python sources before building.")),
+            (
+                "abi_check",
+                string(
+                    "ABI check",
+                    "Tarball of the previous version, relative to the output path, whose shared libraries are compared with abidiff.",
+                ),
+            ),
+            (
+                "abi_check_whitelist",
+                strings(
+                    "ABI check whitelist",
+                    "Symbols whose incompatible ABI changes are intentional.",
+                ),
+            ),
+            (
+                "hermetic",
+                boolean(
+                    "Hermetic",
+                    "Replace PATH with /usr/bin, /bin, /usr/sbin, /sbin and the directories of the makedepends.",
+                ),
+            ),
+            (
+                "hermetic_extra_paths",
+                strings(
+                    "Hermetic extra paths",
+                    "Directories added to the PATH of hermetic builds.",
+                ),
+            ),
+            (
+                "perf_events",
+                boolean(
+                    "Build timing",
+                    "Write the wall clock and CPU time of each build phase to build_timing.json.",
+                ),
+            ),
+            (
+                "check_provides",
+                boolean(
+                    "Check provides",
+                    "Fail when a file or shared library listed in the provides of a package or subpackage is not in its files.",
+                ),
+            ),
+            (
+                "ld_preload",
+                strings(
+                    "LD_PRELOAD",
+                    "Libraries preloaded into the scripts, absolute paths or names looked up with ldconfig.",
+                ),
+            ),
+            (
+                "network_policy",
+                enumeration(
+                    "Network policy",
+                    "Network access of the scripts, allow-list requires root or CAP_NET_ADMIN.",
+                    &["full", "loopback-only", "none", "allow-list"],
+                ),
+            ),
+            (
+                "network_allow_cidrs",
+                strings(
+                    "Allowed networks",
+                    "Networks the scripts may connect to with the allow-list policy, e.g. 10.0.0.0/8.",
+                ),
+            ),
+            (
+                "tempfile_cleanup",
+                boolean(
+                    "Temporary file check",
+                    "Warn about temporary and empty files in the output, e.g. *.log, *.pid or files in /tmp.",
+                ),
+            ),
+            (
+                "tempfile_autoremove",
+                boolean(
+                    "Remove temporary files",
+                    "Remove the temporary files found by tempfile_cleanup instead of warning, empty files are kept.",
+                ),
+            ),
+            (
+                "assert_no_rpath",
+                boolean(
+                    "Assert no RPATH",
+                    "Fail when an ELF file has an RPATH or RUNPATH into the build directory.",
+                ),
+            ),
+            (
+                "rpath_whitelist",
+                strings(
+                    "RPATH whitelist",
+                    "Glob patterns of RPATH entries accepted by assert_no_rpath.",
+                ),
+            ),
+            (
+                "ldconfig_update",
+                boolean(
+                    "Update the linker cache",
+                    "Run ldconfig in post_install.sh and post_upgrade.sh when shared libraries are installed to /lib or /usr/lib. Enabled for versioned libraries unless set to false.",
+                ),
+            ),
+            (
+                "python_path",
+                string(
+                    "Python interpreter",
+                    "Interpreter set as PYTHON and PYTHON3 and linked as python3 and python in PATH.",
+                ),
+            ),
+            (
+                "use_system_python",
+                boolean(
+                    "Use the system python setup",
+                    "Set to false to unset PYTHONPATH and PYTHONSTARTUP for the build.",
+                ),
+            ),
+            (
+                "iwyu",
+                boolean(
+                    "include-what-you-use",
+                    "Run include-what-you-use on the C and C++ sources and write iwyu_suggestions.txt to the output directory. For development only.",
+                ),
+            ),
+            (
+                "iwyu_fix",
+                boolean(
+                    "Apply include-what-you-use suggestions",
+                    "Apply the suggestions to the sources with iwyu_tool.py and fix_includes.py.",
+                ),
+            ),
+        ],
+        required,
+    )
+}
+
+fn output_schema() -> Value {
+    object(
+        "Output",
+        "How the tarballs are written.",
+        vec![
+            (
+                "tarball_owner",
+                string(
+                    "Owner",
+                    "Owner of all files in the tarballs, default is root.",
+                ),
+            ),
+            (
+                "tarball_group",
+                string(
+                    "Group",
+                    "Group of all files in the tarballs, default is root.",
+                ),
+            ),
+            (
+                "tarball_normalize_permissions",
+                boolean("Normalize permissions", "Strip setuid and setgid bits."),
+            ),
+            (
+                "tarball_keep_setuid",
+                strings(
+                    "Keep setuid",
+                    "Files that keep their setuid and setgid bits.",
+                ),
+            ),
+            (
+                "split_arch_dir",
+                boolean(
+                    "Split by architecture",
+                    "Write the output into <output>/<arch>/.",
+                ),
+            ),
+            (
+                "include_pkgfile",
+                boolean(
+                    "Include package.toml",
+                    "Embed package.toml in the tarballs, default is true.",
+                ),
+            ),
+        ],
+        &[],
+    )
+}
+
+fn object(
+    title: &str,
+    description: &str,
+    properties: Vec<(&str, Value)>,
+    required: &[&str],
+) -> Value {
+    let properties = properties
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect::<Map<_, _>>();
+
+    json!({
+        "title": title,
+        "description": description,
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+fn array(title: &str, description: &str, items: Value) -> Value {
+    json!({ "title": title, "description": description, "type": "array", "items": items })
+}
+
+fn string(title: &str, description: &str) -> Value {
+    json!({ "title": title, "description": description, "type": "string" })
+}
+
+fn integer(title: &str, description: &str) -> Value {
+    json!({ "title": title, "description": description, "type": "integer", "minimum": 0 })
+}
+
+fn boolean(title: &str, description: &str) -> Value {
+    json!({ "title": title, "description": description, "type": "boolean" })
+}
+
+fn strings(title: &str, description: &str) -> Value {
+    array(title, description, json!({ "type": "string" }))
+}
+
+fn string_map(title: &str, description: &str) -> Value {
+    json!({
+        "title": title,
+        "description": description,
+        "type": "object",
+        "additionalProperties": { "type": "string" },
+    })
+}
+
+fn enumeration(title: &str, description: &str, values: &[&str]) -> Value {
+    json!({ "title": title, "description": description, "type": "string", "enum": values })
+}
+
+// Validates a value against the subset of JSON Schema used by pkgfile_schema:
+// type, enum, minimum, properties, required, additionalProperties and items.
+fn validate(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let location = if path.is_empty() { "PkgFile" } else { path };
+
+    if let Some(expected) = schema["type"].as_str() {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            _ => true,
+        };
+        if !matches {
+            errors.push(format!(
+                "{}: expected {}, found {}",
+                location,
+                expected,
+                type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            let allowed = allowed
+                .iter()
+                .filter_map(|value| value.as_str())
+                .collect::<Vec<_>>();
+            errors.push(format!(
+                "{}: {} is not one of {}",
+                location,
+                value,
+                allowed.join(", ")
+            ));
+        }
+    }
+
+    if let (Some(minimum), Some(number)) = (schema["minimum"].as_i64(), value.as_i64()) {
+        if number < minimum {
+            errors.push(format!("{}: {} is less than {}", location, number, minimum));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        for required in schema["required"].as_array().into_iter().flatten() {
+            let required = required.as_str().unwrap_or_default();
+            if !object.contains_key(required) {
+                errors.push(format!("{}: missing required field {}", location, required));
+            }
+        }
+
+        for (key, value) in object {
+            let child_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+
+            match (&schema["properties"][key], &schema["additionalProperties"]) {
+                (Value::Null, Value::Bool(false)) => {
+                    errors.push(format!("{}: unknown field", child_path))
+                }
+                (Value::Null, Value::Object(_)) => {
+                    validate(&schema["additionalProperties"], value, &child_path, errors)
+                }
+                (Value::Null, _) => {}
+                (property, _) => validate(property, value, &child_path, errors),
+            }
+        }
+    }
+
+    if let Some(array) = value.as_array() {
+        if schema["items"].is_object() {
+            for (index, item) in array.iter().enumerate() {
+                validate(
+                    &schema["items"],
+                    item,
+                    &format!("{}[{}]", location, index),
+                    errors,
+                );
+            }
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
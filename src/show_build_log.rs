@@ -0,0 +1,82 @@
+use crate::{archive, cli, util, PkgBuilderError};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// pkgbuilder show-build-log <TARBALL> [--tail <N>] [--search <PATTERN>]
+//
+// Prints the build log embedded with pkgbuilder build --save-log.
+// With --search only the lines containing PATTERN are printed,
+// with --tail only the last N lines (after --search).
+pub fn run(mut args: Vec<String>) {
+    let tail = cli::take_option(&mut args, "--tail")
+        .map(|tail| tail.parse::<usize>().expect("--tail must be a number"));
+    let search = cli::take_option(&mut args, "--search");
+    let tarball = args.first().expect("No tarball provided");
+
+    let Some(compressed) = archive::read_entry(tarball, "./build.log.zst") else {
+        eprintln!(
+            "{}",
+            PkgBuilderError::BuildLogMissing {
+                tarball: tarball.to_string()
+            }
+        );
+        std::process::exit(1);
+    };
+
+    let log = decompress(&compressed);
+    let mut lines = log
+        .lines()
+        .filter(|line| search.as_ref().is_none_or(|pattern| line.contains(pattern)))
+        .collect::<Vec<_>>();
+    if let Some(tail) = tail {
+        lines.drain(..lines.len().saturating_sub(tail));
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    for line in lines {
+        // stops quietly when piped into e.g. head
+        if writeln!(stdout, "{}", line).is_err() {
+            return;
+        }
+    }
+}
+
+fn decompress(compressed: &[u8]) -> String {
+    if !util::command_exists("zstd") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "zstd".to_string()
+            }
+        );
+    }
+
+    let mut child = Command::new("zstd")
+        .arg("-dcq")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+
+    // written from a thread so a large log can't fill both pipes
+    let mut stdin = child.stdin.take().expect("Failed to open stdin");
+    let compressed = compressed.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&compressed));
+
+    let output = child.wait_with_output().expect("Failed to wait on child");
+    writer
+        .join()
+        .expect("Failed to write to stdin")
+        .expect("Failed to write to stdin");
+    if !output.status.success() {
+        panic!(
+            "Unable to decompress build log: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
@@ -0,0 +1,274 @@
+use crate::{cli, config, util, PkgBuilderError};
+use std::{
+    fs,
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// pkgbuilder sign-key generate --method <gpg|minisign> --name <NAME>
+// pkgbuilder sign-key list
+// pkgbuilder sign-key export <KEY_ID> [--public|--private]
+// pkgbuilder sign-key import <FILE>
+//
+// Manages signing keys in a keystore separate from the user's keyring.
+// GPG keys live in a dedicated GNUPGHOME, minisign keys are stored as
+// <name>.pub and <name>.key files, importing a secret key also imports the
+// .pub next to it or recreates it with minisign -R.
+pub fn run(mut args: Vec<String>) {
+    if args.is_empty() {
+        panic!("No sign-key command provided");
+    }
+
+    let keystore = keystore_dir();
+    fs::create_dir_all(keystore.join("gnupg")).expect("Unable to create keystore directory");
+    // the keystore holds unencrypted minisign secret keys,
+    // gpg refuses to use a home directory that is readable by others
+    for dir in [keystore.clone(), keystore.join("gnupg")] {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .expect("Unable to set keystore permissions");
+    }
+
+    match args.remove(0).as_str() {
+        "generate" => generate(args, &keystore),
+        "list" => list(&keystore),
+        "export" => export(args, &keystore),
+        "import" => import(args, &keystore),
+        command => panic!("Unknown sign-key command: {}", command),
+    }
+}
+
+fn keystore_dir() -> PathBuf {
+    config::config_dir().join("keys")
+}
+
+fn gpg(keystore: &Path) -> Command {
+    if !util::command_exists("gpg") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "gpg".to_string()
+            }
+        );
+    }
+
+    let mut command = Command::new("gpg");
+    command.arg("--homedir").arg(keystore.join("gnupg"));
+    command
+}
+
+fn minisign() -> Command {
+    if !util::command_exists("minisign") {
+        panic!(
+            "{}",
+            PkgBuilderError::ToolMissing {
+                tool: "minisign".to_string()
+            }
+        );
+    }
+
+    Command::new("minisign")
+}
+
+fn check_status(status: std::process::ExitStatus, action: &str) {
+    if !status.success() {
+        panic!("Failed to {}", action);
+    }
+}
+
+// secret keys are only readable by their owner
+fn restrict_secret_key(path: &Path) {
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .expect("Unable to set key permissions");
+}
+
+fn generate(mut args: Vec<String>, keystore: &Path) {
+    let method = cli::take_option(&mut args, "--method").unwrap_or_else(|| "gpg".to_string());
+    let name = cli::take_option(&mut args, "--name").expect("No key name provided");
+
+    match method.as_str() {
+        "gpg" => {
+            let status = gpg(keystore)
+                .arg("--batch")
+                .arg("--passphrase")
+                .arg("")
+                .arg("--quick-generate-key")
+                .arg(&name)
+                .arg("default")
+                .arg("default")
+                .arg("never")
+                .status()
+                .expect("Failed to execute command");
+            check_status(status, "generate gpg key");
+        }
+        "minisign" => {
+            let public_key = keystore.join(format!("{}.pub", name));
+            if public_key.exists() {
+                panic!("A minisign key named {} already exists", name);
+            }
+
+            let status = minisign()
+                .arg("-G")
+                // the keystore keys are not password protected
+                .arg("-W")
+                .arg("-p")
+                .arg(&public_key)
+                .arg("-s")
+                .arg(keystore.join(format!("{}.key", name)))
+                .status()
+                .expect("Failed to execute command");
+            check_status(status, "generate minisign key");
+            restrict_secret_key(&keystore.join(format!("{}.key", name)));
+        }
+        method => panic!("Unknown signing method: {}", method),
+    }
+
+    println!("Generated {} key {}", method, name);
+}
+
+fn list(keystore: &Path) {
+    let signing_key_id = config::GlobalConfig::load().signing_key_id;
+    if let Some(ref signing_key_id) = signing_key_id {
+        println!("Configured signing key: {}", signing_key_id);
+    }
+
+    if util::command_exists("gpg") {
+        println!("GPG keys:");
+        let status = gpg(keystore)
+            .arg("--list-secret-keys")
+            .arg("--with-fingerprint")
+            .status()
+            .expect("Failed to execute command");
+        check_status(status, "list gpg keys");
+    }
+
+    println!("Minisign keys:");
+    for name in minisign_keys(keystore) {
+        let public_key = fs::read_to_string(keystore.join(format!("{}.pub", name)))
+            .expect("Unable to read public key");
+        // the comment line of a minisign public key contains its key id
+        let key_id = public_key
+            .lines()
+            .next()
+            .and_then(|comment| comment.rsplit(' ').next())
+            .unwrap_or_default();
+        println!("{} {}", name, key_id);
+    }
+}
+
+fn minisign_keys(keystore: &Path) -> Vec<String> {
+    let mut names = fs::read_dir(keystore)
+        .expect("Unable to read keystore directory")
+        .map(|entry| entry.expect("Unable to read directory entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "pub"))
+        .map(|path| path.file_stem().unwrap().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn export(mut args: Vec<String>, keystore: &Path) {
+    let private = cli::take_flag(&mut args, "--private");
+    // public is the default
+    cli::take_flag(&mut args, "--public");
+    let key_id = args.first().expect("No key id provided");
+
+    if minisign_keys(keystore).contains(key_id) {
+        let extension = if private { "key" } else { "pub" };
+        let key = fs::read_to_string(keystore.join(format!("{}.{}", key_id, extension)))
+            .expect("Unable to read key");
+        print!("{}", key);
+        return;
+    }
+
+    let status = gpg(keystore)
+        .arg("--armor")
+        .arg(if private {
+            "--export-secret-keys"
+        } else {
+            "--export"
+        })
+        .arg(key_id)
+        .status()
+        .expect("Failed to execute command");
+    check_status(status, "export gpg key");
+}
+
+fn import(args: Vec<String>, keystore: &Path) {
+    let file = args.first().expect("No key file provided");
+    let contents = fs::read_to_string(file).expect("Unable to read key file");
+
+    if contents.starts_with("untrusted comment: minisign") {
+        let name = Path::new(file)
+            .file_stem()
+            .expect("Invalid key file name")
+            .to_string_lossy();
+        let extension = if contents.contains("secret key") {
+            "key"
+        } else {
+            "pub"
+        };
+
+        let imported = keystore.join(format!("{}.{}", name, extension));
+        if extension == "pub" {
+            fs::write(&imported, &contents).expect("Unable to import key");
+            println!("Imported minisign key {}", name);
+            return;
+        }
+
+        // keys are listed and exported by their public key, taken from
+        // next to the secret key or recreated from it
+        let public_key = keystore.join(format!("{}.pub", name));
+        let source_public_key = Path::new(file).with_extension("pub");
+        // checked before anything is imported
+        let recreate = (!source_public_key.is_file()).then(minisign);
+
+        // the secret key is never readable by others, not even before its mode is set
+        let mut key = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&imported)
+            .expect("Unable to import key");
+        // mode only applies to new files
+        restrict_secret_key(&imported);
+        key.write_all(contents.as_bytes())
+            .expect("Unable to import key");
+
+        match recreate {
+            None => {
+                fs::copy(&source_public_key, &public_key).expect("Unable to import public key");
+            }
+            Some(mut minisign) => {
+                let status = minisign
+                    .arg("-R")
+                    .arg("-s")
+                    .arg(&imported)
+                    .arg("-p")
+                    .arg(&public_key)
+                    .status()
+                    .expect("Failed to execute command");
+                if !status.success() {
+                    fs::remove_file(&imported).expect("Unable to remove imported key");
+                    panic!(
+                        "Unable to recreate the public key of {}, provide {}",
+                        name,
+                        source_public_key.display()
+                    );
+                }
+            }
+        }
+        println!("Imported minisign key {}", name);
+        return;
+    }
+
+    let status = gpg(keystore)
+        .arg("--batch")
+        .arg("--import")
+        .arg(file)
+        .status()
+        .expect("Failed to execute command");
+    check_status(status, "import gpg key");
+}
@@ -0,0 +1,141 @@
+use serde::Serialize;
+use std::{fs, process::Command, time::Instant};
+
+// Wall clock and CPU time of the build phases for build.perf_events.
+// The CPU time includes the scripts and tools pkgbuilder waited for.
+pub struct BuildTimer {
+    start: Instant,
+    cpu_start: f64,
+    phases: Vec<PhaseTiming>,
+    // the running phase, its start and its CPU time at the start
+    current: Option<(PhaseTiming, Instant, f64)>,
+}
+
+#[derive(Clone, Serialize)]
+struct PhaseTiming {
+    phase: String,
+    // set for the package_subpackage phases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subpackage: Option<String>,
+    wall_seconds: f64,
+    cpu_seconds: f64,
+}
+
+#[derive(Serialize)]
+struct BuildTiming<'a> {
+    package: &'a str,
+    total_wall_seconds: f64,
+    total_cpu_seconds: f64,
+    phases: &'a [PhaseTiming],
+}
+
+impl BuildTimer {
+    pub fn new() -> BuildTimer {
+        BuildTimer {
+            start: Instant::now(),
+            cpu_start: cpu_seconds(),
+            phases: Vec::new(),
+            current: None,
+        }
+    }
+
+    // ends the running phase and starts the next one
+    pub fn phase(&mut self, phase: &str) {
+        self.start_phase(phase, None);
+    }
+
+    pub fn subpackage_phase(&mut self, subpackage: &str) {
+        self.start_phase("package_subpackage", Some(subpackage));
+    }
+
+    fn start_phase(&mut self, phase: &str, subpackage: Option<&str>) {
+        self.finish();
+        let timing = PhaseTiming {
+            phase: phase.to_string(),
+            subpackage: subpackage.map(|subpackage| subpackage.to_string()),
+            wall_seconds: 0.0,
+            cpu_seconds: 0.0,
+        };
+        self.current = Some((timing, Instant::now(), cpu_seconds()));
+    }
+
+    // ends the running phase
+    pub fn finish(&mut self) {
+        if let Some((mut timing, start, cpu_start)) = self.current.take() {
+            timing.wall_seconds = start.elapsed().as_secs_f64();
+            timing.cpu_seconds = cpu_seconds() - cpu_start;
+            self.phases.push(timing);
+        }
+    }
+
+    // writes <output_path>/build_timing.json and prints a summary
+    pub fn report(&mut self, package: &str, output_path: &str) {
+        self.finish();
+
+        let timing = BuildTiming {
+            package,
+            total_wall_seconds: self.start.elapsed().as_secs_f64(),
+            total_cpu_seconds: cpu_seconds() - self.cpu_start,
+            phases: &self.phases,
+        };
+
+        println!("{:<40} {:>10} {:>10}", "Phase", "Wall (s)", "CPU (s)");
+        for phase in timing.phases {
+            let name = match phase.subpackage {
+                Some(ref subpackage) => format!("{} ({})", phase.phase, subpackage),
+                None => phase.phase.clone(),
+            };
+            println!(
+                "{:<40} {:>10.2} {:>10.2}",
+                name, phase.wall_seconds, phase.cpu_seconds
+            );
+        }
+        println!(
+            "{:<40} {:>10.2} {:>10.2}",
+            "total", timing.total_wall_seconds, timing.total_cpu_seconds
+        );
+
+        let path = format!("{}/build_timing.json", output_path);
+        fs::write(
+            &path,
+            serde_json::to_string_pretty(&timing).expect("Unable to serialize build timing"),
+        )
+        .expect("Unable to write build timing");
+        println!("Wrote build timing to {}", path);
+    }
+}
+
+// user and system time of pkgbuilder and its waited-for children
+// from /proc/self/stat, 0 where it isn't available
+fn cpu_seconds() -> f64 {
+    let Ok(stat) = fs::read_to_string("/proc/self/stat") else {
+        return 0.0;
+    };
+
+    // the command name in parentheses may contain spaces,
+    // utime, stime, cutime and cstime are fields 14 to 17
+    let Some((_, fields)) = stat.rsplit_once(')') else {
+        return 0.0;
+    };
+    let ticks: u64 = fields
+        .split_whitespace()
+        .skip(11)
+        .take(4)
+        .filter_map(|field| field.parse::<u64>().ok())
+        .sum();
+
+    ticks as f64 / clock_ticks() as f64
+}
+
+// clock ticks per second the /proc times are counted in
+fn clock_ticks() -> u64 {
+    static CLOCK_TICKS: std::sync::OnceLock<u64> = std::sync::OnceLock::new();
+    *CLOCK_TICKS.get_or_init(|| {
+        Command::new("getconf")
+            .arg("CLK_TCK")
+            .output()
+            .ok()
+            .and_then(|output| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+            .unwrap_or(100)
+    })
+}
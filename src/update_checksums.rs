@@ -0,0 +1,114 @@
+use crate::{cli, config::GlobalConfig, download, read_pkgfile, release};
+use std::{fs, process::Command};
+use toml_edit::{value, Document};
+
+// pkgbuilder update-checksums <PKGFILE> [--update-git-commits]
+//
+// Downloads every archive source of a PkgFile and writes its checksum
+// into the PkgFile, keeping the rest of the file as it is.
+// With --update-git-commits, git_commit is set to the current
+// commit of git_ref (or HEAD) for git sources pinning a commit.
+pub fn run(mut args: Vec<String>) {
+    let update_git_commits = cli::take_flag(&mut args, "--update-git-commits");
+    let file_path = args.first().expect("No file path provided");
+
+    let algorithm = GlobalConfig::load()
+        .default_checksum_algorithm
+        .unwrap_or_else(|| "sha256".to_string());
+    if !["md5", "sha1", "sha256", "sha512"].contains(&algorithm.as_str()) {
+        panic!("Unknown checksum algorithm: {}", algorithm);
+    }
+
+    let mut pkgfile = read_pkgfile(file_path);
+    for source in pkgfile.source.iter_mut().flatten() {
+        release::resolve(source);
+    }
+    let contents = fs::read_to_string(file_path).expect("Unable to read the file");
+    let mut document = contents
+        .parse::<Document>()
+        .expect("Unable to parse the TOML file");
+
+    let Some(sources) = document
+        .get_mut("source")
+        .and_then(|sources| sources.as_array_of_tables_mut())
+    else {
+        println!("{} has no sources", file_path);
+        return;
+    };
+
+    fs::create_dir_all("/tmp/pkgbuilder").expect("Unable to create download directory");
+    let mut updated = 0;
+
+    for (source, table) in pkgfile.source.iter().flatten().zip(sources.iter_mut()) {
+        let old = table
+            .get("checksum")
+            .and_then(|checksum| checksum.as_str())
+            .map(|checksum| checksum.to_string());
+
+        if source.archive_extension().is_some() {
+            let file = format!(
+                "/tmp/pkgbuilder/update-checksums-{}.tmpdownload",
+                std::process::id()
+            );
+            if !download::fetch(source, &file) {
+                eprintln!("Error: unable to download {}", source.source);
+                continue;
+            }
+            let new = download::checksum_file(&file, &algorithm);
+            fs::remove_file(&file).expect("Unable to remove download");
+
+            println!(
+                "{}: {} -> {}",
+                source.source,
+                old.as_deref().unwrap_or("none"),
+                new
+            );
+            if old.as_deref() != Some(new.as_str()) {
+                table["checksum"] = value(new);
+                updated += 1;
+            }
+        } else if update_git_commits && source.source.ends_with(".git") {
+            let Some(ref old) = source.git_commit else {
+                continue;
+            };
+            let Some(new) = remote_commit(&source.source, source.git_ref.as_deref()) else {
+                eprintln!("Error: unable to resolve the commit of {}", source.source);
+                continue;
+            };
+
+            println!("{}: {} -> {}", source.source, old, new);
+            if *old != new {
+                table["git_commit"] = value(new);
+                updated += 1;
+            }
+        }
+    }
+
+    if updated > 0 {
+        fs::write(file_path, document.to_string()).expect("Unable to write the PkgFile");
+    }
+    println!("Updated {} source(s) in {}", updated, file_path);
+}
+
+// the commit a branch or tag of a remote repository points to
+fn remote_commit(url: &str, git_ref: Option<&str>) -> Option<String> {
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg(url)
+        .arg(git_ref.unwrap_or("HEAD"))
+        .output()
+        .expect("Failed to execute command");
+    if !output.status.success() {
+        return None;
+    }
+
+    // annotated tags are listed twice, the peeled ^{} entry is the commit
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    lines
+        .iter()
+        .find(|line| line.ends_with("^{}"))
+        .or(lines.first())
+        .and_then(|line| line.split_whitespace().next())
+        .map(|commit| commit.to_string())
+}
@@ -0,0 +1,107 @@
+use crate::{archive, cli, installed, manifest, util, PkgFile};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+// pkgbuilder upgrade <PACKAGE_NAME> <TARBALL> [--sysroot <PATH>]
+//
+// Replaces an installed package with a newer tarball.
+// Files that are no longer part of the package are removed and
+// modified backup files are kept, the new version is written next to them
+// as <file>.pacnew.
+pub fn run(mut args: Vec<String>) {
+    let sysroot = cli::take_option(&mut args, "--sysroot").unwrap_or_else(|| "/".to_string());
+    let name = args.first().expect("No package name provided");
+    let tarball = args.get(1).expect("No tarball provided");
+
+    let database_dir = installed::package_dir(&sysroot, name);
+    let old_files = fs::read_to_string(database_dir.join("FILES"))
+        .unwrap_or_else(|_| panic!("Package {} is not installed", name));
+    let old_files = manifest::parse(&old_files);
+
+    let new_files_raw =
+        archive::read_entry(tarball, "./FILES").expect("Tarball has no FILES manifest");
+    let new_files = manifest::parse(&String::from_utf8_lossy(&new_files_raw));
+
+    // tarballs built with include_pkgfile = false have no package.toml,
+    // they are upgraded from their FILES manifest alone
+    let metadata = archive::read_entry(tarball, "./package.toml");
+    let backups = match metadata {
+        Some(ref metadata) => {
+            let pkgfile: PkgFile = toml::from_str(&String::from_utf8_lossy(metadata))
+                .expect("Unable to parse package.toml");
+            pkgfile.package.backup.unwrap_or_default()
+        }
+        None => {
+            eprintln!(
+                "Warning: {} has no package.toml, modified configuration files are overwritten",
+                tarball
+            );
+            Vec::new()
+        }
+    };
+
+    archive::run_hook(tarball, "pre_upgrade.sh", &sysroot);
+
+    // remove files that are not part of the new version anymore
+    for entry in &old_files {
+        if new_files.iter().any(|new| new.path == entry.path) {
+            continue;
+        }
+
+        let path = format!("{}{}", sysroot.trim_end_matches('/'), entry.path);
+        if fs::remove_file(&path).is_ok() {
+            println!("Removed {}", entry.path);
+        }
+    }
+
+    // backup files that were changed since they were installed are kept
+    let old_checksums = old_files
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry.sha256.as_str()))
+        .collect::<HashMap<_, _>>();
+
+    let mut modified_backups = Vec::new();
+    for backup in &backups {
+        let path = PathBuf::from(format!("{}{}", sysroot.trim_end_matches('/'), backup));
+        let Some(old_checksum) = old_checksums.get(backup.as_str()) else {
+            continue;
+        };
+        if !path.is_file() {
+            continue;
+        }
+
+        if util::sha256_files(&[path])[0] != *old_checksum {
+            modified_backups.push(backup.clone());
+        }
+    }
+
+    let mut exclude = manifest::METADATA_FILES
+        .iter()
+        .map(|file| format!("./{}", file))
+        .collect::<Vec<_>>();
+    exclude.extend(modified_backups.iter().map(|backup| format!(".{}", backup)));
+
+    archive::extract_tarball(tarball, &sysroot, &exclude).unwrap_or_else(|err| panic!("{}", err));
+
+    for backup in &modified_backups {
+        let contents = archive::read_entry(tarball, &format!(".{}", backup))
+            .expect("Unable to read backup file from tarball");
+        let pacnew = format!("{}{}.pacnew", sysroot.trim_end_matches('/'), backup);
+        fs::write(&pacnew, contents).expect("Unable to write .pacnew file");
+        println!(
+            "{} was modified, new version written to {}.pacnew",
+            backup, backup
+        );
+    }
+
+    archive::run_hook(tarball, "post_upgrade.sh", &sysroot);
+
+    // update the installed-package database
+    if let Some(metadata) = metadata {
+        fs::write(database_dir.join("package.toml"), metadata)
+            .expect("Unable to update installed package database");
+    }
+    fs::write(database_dir.join("FILES"), new_files_raw)
+        .expect("Unable to update installed package database");
+
+    println!("Upgraded {}", name);
+}
@@ -0,0 +1,143 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// checks whether an executable is available in PATH
+pub fn command_exists(command: &str) -> bool {
+    Command::new("sh")
+        .arg("-c")
+        .arg(format!("command -v {}", command))
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+// returns all files below a path, the path itself if it is a file
+pub fn walk_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    if !path.is_dir() || path.is_symlink() {
+        if path.symlink_metadata().is_ok() {
+            files.push(path.to_path_buf());
+        }
+        return files;
+    }
+
+    for entry in fs::read_dir(path).expect("Unable to read directory") {
+        let entry = entry.expect("Unable to read directory entry");
+        files.extend(walk_files(&entry.path()));
+    }
+
+    files
+}
+
+// total size of all files below a path in bytes
+pub fn disk_usage(path: &Path) -> u64 {
+    walk_files(path)
+        .iter()
+        .filter_map(|file| file.symlink_metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// matches a path against a glob pattern,
+// `*` and `?` don't match `/`, `**` matches across directories.
+// patterns without a `/` are matched against the file name only
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    if !pattern.contains('/') {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        return glob_match_bytes(pattern.as_bytes(), file_name.as_bytes());
+    }
+
+    glob_match_bytes(pattern.as_bytes(), path.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            // `**/` also matches no directory at all
+            if rest.first() == Some(&b'/') && glob_match_bytes(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_match_bytes(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match_bytes(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => {
+            matches!(text.first(), Some(c) if *c != b'/')
+                && glob_match_bytes(&pattern[1..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match_bytes(&pattern[1..], &text[1..]),
+    }
+}
+
+// sha256 checksums of files, in the same order as the given paths
+pub fn sha256_files(files: &[PathBuf]) -> Vec<String> {
+    let mut checksums = Vec::with_capacity(files.len());
+
+    // keep the command line below the argument size limit
+    for chunk in files.chunks(512) {
+        let output = Command::new("sha256sum")
+            .arg("--")
+            .args(chunk)
+            .output()
+            .expect("Failed to execute command");
+
+        if !output.status.success() {
+            panic!(
+                "Failed to compute checksums: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        checksums.extend(String::from_utf8_lossy(&output.stdout).lines().map(|line| {
+            line.split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        }));
+    }
+
+    checksums
+}
+
+pub fn is_root() -> bool {
+    id(&["-u"]).is_some_and(|uid| uid == 0)
+}
+
+// uid and primary gid of a user, None if the user doesn't exist
+pub fn user_ids(name: &str) -> Option<(u32, u32)> {
+    Some((id(&["-u", name])?, id(&["-g", name])?))
+}
+
+fn id(args: &[&str]) -> Option<u32> {
+    let output = Command::new("id").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+// checks the ELF magic number of a file
+pub fn is_elf(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == *b"\x7fELF"
+}
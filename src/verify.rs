@@ -0,0 +1,131 @@
+use crate::{cli, expand_file_selector, read_pkgfile, util, PkgBuilderError, PkgFile};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+// pkgbuilder verify <PKGFILE> <OUT_DIR> [--allow-overlap]
+//
+// Checks a PkgFile against the contents of an already built out directory.
+pub fn run(mut args: Vec<String>) {
+    let allow_overlap = cli::take_flag(&mut args, "--allow-overlap");
+    let file_path = args.first().expect("No file path provided");
+    let out_dir = args.get(1).expect("No out directory provided");
+
+    let pkgfile = read_pkgfile(file_path);
+
+    let result = check_overlaps(&pkgfile, out_dir, allow_overlap)
+        .and_then(|_| check_subpackage_sizes(&pkgfile, out_dir));
+
+    match result {
+        Ok(()) => println!("PkgFile verified successfully"),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Expands the file selectors of all subpackages and reports files
+// that are selected by more than one subpackage.
+// With allow_overlap the overlaps are only reported as warnings.
+pub fn check_overlaps(
+    pkgfile: &PkgFile,
+    out_dir: &str,
+    allow_overlap: bool,
+) -> Result<(), PkgBuilderError> {
+    let Some(ref subpackages) = pkgfile.subpackage else {
+        return Ok(());
+    };
+
+    // file path -> names of the subpackages selecting it
+    let mut claims: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+
+    for subpackage in subpackages {
+        for file_selector in &subpackage.files {
+            for path in expand_file_selector(out_dir, file_selector) {
+                // a selector matching a directory claims everything below it
+                for file in util::walk_files(Path::new(&format!("{}{}", out_dir, path))) {
+                    let file = file.to_string_lossy().replace(out_dir, "");
+                    let names = claims.entry(file).or_default();
+                    if !names.contains(&subpackage.name.as_str()) {
+                        names.push(&subpackage.name);
+                    }
+                }
+            }
+        }
+    }
+
+    let overlaps = claims
+        .iter()
+        .filter(|(_, names)| names.len() > 1)
+        .collect::<Vec<_>>();
+
+    for (file, names) in &overlaps {
+        let level = if allow_overlap { "Warning" } else { "Error" };
+        eprintln!(
+            "{}: {} is selected by multiple subpackages: {}",
+            level,
+            file,
+            names.join(", ")
+        );
+    }
+
+    if overlaps.is_empty() || allow_overlap {
+        Ok(())
+    } else {
+        Err(PkgBuilderError::OverlappingSubpackages {
+            count: overlaps.len(),
+        })
+    }
+}
+
+// Checks the total size of the files selected by each subpackage
+// against its install_size_limit_mb and install_size_minimum_kb.
+pub fn check_subpackage_sizes(pkgfile: &PkgFile, out_dir: &str) -> Result<(), PkgBuilderError> {
+    let Some(ref subpackages) = pkgfile.subpackage else {
+        return Ok(());
+    };
+
+    for subpackage in subpackages {
+        if subpackage.install_size_limit_mb.is_none()
+            && subpackage.install_size_minimum_kb.is_none()
+        {
+            continue;
+        }
+
+        let mut files = BTreeSet::new();
+        for file_selector in &subpackage.files {
+            for path in expand_file_selector(out_dir, file_selector) {
+                files.extend(util::walk_files(Path::new(&format!("{}{}", out_dir, path))));
+            }
+        }
+        let size: u64 = files
+            .iter()
+            .filter_map(|file| file.symlink_metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        if let Some(limit_mb) = subpackage.install_size_limit_mb {
+            if size > limit_mb.saturating_mul(1024 * 1024) {
+                return Err(PkgBuilderError::SubpackageTooLarge {
+                    name: subpackage.name.clone(),
+                    actual_mb: size.div_ceil(1024 * 1024),
+                    limit_mb,
+                });
+            }
+        }
+
+        if let Some(minimum_kb) = subpackage.install_size_minimum_kb {
+            if size < minimum_kb.saturating_mul(1024) {
+                return Err(PkgBuilderError::SubpackageTooSmall {
+                    name: subpackage.name.clone(),
+                    actual_kb: size / 1024,
+                    minimum_kb,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
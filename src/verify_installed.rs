@@ -0,0 +1,96 @@
+use crate::{cli, installed, manifest, util, PkgFile};
+use std::{fs, path::PathBuf};
+
+// pkgbuilder verify-installed <PACKAGE_NAME> [--sysroot <PATH>] [--quiet]
+//
+// Checks the files of an installed package against its FILES manifest.
+// Changed backup files are reported as modified by the user,
+// any other changed file as corrupted.
+// Exits with 0 if all files are intact, 1 if only backup files were modified
+// and 2 if files are corrupted.
+// With --quiet, only changed files are printed.
+pub fn run(mut args: Vec<String>) {
+    let sysroot = cli::take_option(&mut args, "--sysroot").unwrap_or_else(|| "/".to_string());
+    let quiet = cli::take_flag(&mut args, "--quiet");
+    let name = args.first().expect("No package name provided");
+
+    let database_dir = installed::package_dir(&sysroot, name);
+    let files = fs::read_to_string(database_dir.join("FILES"))
+        .unwrap_or_else(|_| panic!("Package {} is not installed", name));
+    let entries = manifest::parse(&files);
+
+    let backup = fs::read_to_string(database_dir.join("package.toml"))
+        .ok()
+        .and_then(|metadata| toml::from_str::<PkgFile>(&metadata).ok())
+        .and_then(|pkgfile| pkgfile.package.backup)
+        .unwrap_or_default();
+
+    let paths = entries
+        .iter()
+        .map(|entry| PathBuf::from(format!("{}{}", sysroot.trim_end_matches('/'), entry.path)))
+        .collect::<Vec<_>>();
+
+    // only regular files that still exist are hashed
+    let hashed = entries
+        .iter()
+        .zip(&paths)
+        .filter(|(entry, path)| entry.sha256 != "-" && path.is_file())
+        .map(|(_, path)| path.clone())
+        .collect::<Vec<_>>();
+    let mut checksums = util::sha256_files(&hashed).into_iter();
+
+    let mut modified = 0;
+    let mut corrupted = 0;
+
+    for (entry, path) in entries.iter().zip(&paths) {
+        let problem = if path.symlink_metadata().is_err() {
+            Some("file missing")
+        } else if entry.sha256 == "-" {
+            None
+        } else if !path.is_file() {
+            Some("not a regular file")
+        } else {
+            let checksum = checksums.next().unwrap_or_default();
+            let size = path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            if size != entry.size {
+                Some("size mismatch")
+            } else if checksum != entry.sha256 {
+                Some("hash mismatch")
+            } else {
+                None
+            }
+        };
+
+        match problem {
+            None => {
+                if !quiet {
+                    println!("ok: {}", entry.path);
+                }
+            }
+            Some(problem) if backup.contains(&entry.path) => {
+                eprintln!("Warning: modified by user ({}): {}", problem, entry.path);
+                modified += 1;
+            }
+            Some(problem) => {
+                eprintln!("Error: corrupted ({}): {}", problem, entry.path);
+                corrupted += 1;
+            }
+        }
+    }
+
+    if !quiet {
+        println!(
+            "{} file(s) checked, {} modified by user, {} corrupted",
+            entries.len(),
+            modified,
+            corrupted
+        );
+    }
+
+    if corrupted > 0 {
+        std::process::exit(2);
+    }
+    if modified > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{cli, repo, util};
+use std::{fs, path::PathBuf};
+
+// pkgbuilder verify-repo <REPO_DIR> [--fix]
+//
+// Audits a package repository against its PACKAGES index.
+// With --fix, tarballs missing from the index are removed, unless they
+// have no package.toml, and the index is regenerated. Missing and corrupted tarballs can't be
+// fixed, the index is left as it is and the exit code is 1.
+pub fn run(mut args: Vec<String>) {
+    let fix = cli::take_flag(&mut args, "--fix");
+    let repo_dir = args.first().expect("No repository path provided");
+
+    let index = repo::read_index(repo_dir);
+    let mut errors = 0;
+    // problems a regenerated index would hide
+    let mut unfixable = 0;
+
+    for entry in &index.package {
+        let path = format!("{}/{}", repo_dir, entry.filename);
+        if !PathBuf::from(&path).is_file() {
+            eprintln!("{}: listed in the index but missing", entry.filename);
+            errors += 1;
+            unfixable += 1;
+            continue;
+        }
+
+        if let Some(ref sha256) = entry.sha256 {
+            if util::sha256_files(&[PathBuf::from(&path)])[0] != *sha256 {
+                eprintln!("{}: checksum mismatch", entry.filename);
+                errors += 1;
+                unfixable += 1;
+            }
+        }
+
+        let Some(pkgfile) = repo::read_package_metadata(&path) else {
+            // packages built with output.include_pkgfile = false
+            // are identified by their FILES manifest
+            match repo::read_manifest_package(&path) {
+                Some(package) => {
+                    if repo::package_name(&entry.filename) != Some(package.name.as_str())
+                        || entry.name != package.name
+                    {
+                        eprintln!(
+                            "{}: file name does not match package {}",
+                            entry.filename, entry.name
+                        );
+                        errors += 1;
+                    }
+                    if entry.version != package.version
+                        || entry.pkgrel.unwrap_or(1) != package.pkgrel
+                    {
+                        eprintln!(
+                            "{}: index lists version {}-{} but FILES has {}-{}",
+                            entry.filename,
+                            entry.version,
+                            entry.pkgrel.unwrap_or(1),
+                            package.version,
+                            package.pkgrel
+                        );
+                        errors += 1;
+                    }
+                }
+                None => {
+                    eprintln!("{}: package.toml is missing or invalid", entry.filename);
+                    errors += 1;
+                    unfixable += 1;
+                }
+            }
+            continue;
+        };
+
+        // subpackage tarballs embed the package.toml of the package they were split from
+        let names = std::iter::once(&pkgfile.package.name)
+            .chain(
+                pkgfile
+                    .subpackage
+                    .iter()
+                    .flatten()
+                    .map(|subpackage| &subpackage.name),
+            )
+            .collect::<Vec<_>>();
+
+        if repo::package_name(&entry.filename) != Some(entry.name.as_str())
+            || !names.contains(&&entry.name)
+        {
+            eprintln!(
+                "{}: file name does not match package {}",
+                entry.filename, entry.name
+            );
+            errors += 1;
+        }
+
+        if entry.version != pkgfile.package.version
+            || entry.pkgrel.unwrap_or(1) != pkgfile.package.pkgrel.unwrap_or(1)
+        {
+            eprintln!(
+                "{}: index lists version {}-{} but package.toml has {}-{}",
+                entry.filename,
+                entry.version,
+                entry.pkgrel.unwrap_or(1),
+                pkgfile.package.version,
+                pkgfile.package.pkgrel.unwrap_or(1)
+            );
+            errors += 1;
+        }
+    }
+
+    for tarball in repo::list_tarballs(repo_dir) {
+        if index.package.iter().any(|entry| entry.filename == tarball) {
+            continue;
+        }
+
+        let path = format!("{}/{}", repo_dir, tarball);
+        if fix && repo::read_package_metadata(&path).is_none() {
+            // may be a package built without package.toml, never deleted
+            eprintln!(
+                "{}: not listed in the index and has no package.toml, keeping it",
+                tarball
+            );
+        } else if fix {
+            fs::remove_file(&path).expect("Unable to remove unlisted tarball");
+            println!("{}: not listed in the index, removed", tarball);
+        } else {
+            eprintln!("{}: not listed in the index", tarball);
+            errors += 1;
+        }
+    }
+
+    if fix && unfixable > 0 {
+        eprintln!(
+            "Not regenerating the index, {} problem(s) in {} can't be fixed",
+            unfixable, repo_dir
+        );
+        std::process::exit(1);
+    }
+
+    if fix {
+        repo::write_index(repo_dir, &repo::generate_index(repo_dir));
+        println!("Regenerated the package index");
+        return;
+    }
+
+    if errors > 0 {
+        eprintln!("Found {} problem(s) in {}", errors, repo_dir);
+        std::process::exit(1);
+    }
+
+    println!("Repository verified successfully");
+}
@@ -0,0 +1,131 @@
+use crate::{config, PkgFile};
+use std::{fs, path::Path, process::Command};
+
+// Git sources with git_worktree are checked out as worktrees of a bare
+// repository shared between builds, in <cache dir>/git/<url>.git.
+// The bare repository is kept, the worktrees are removed after the build.
+
+// the shared bare repository of a source url
+pub fn bare_repo(url: &str) -> String {
+    let name = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    config::cache_dir()
+        .join("git")
+        .join(name)
+        .to_string_lossy()
+        .to_string()
+}
+
+// clones or fetches the bare repository and adds a detached worktree
+// at destination for the revision, HEAD if None
+pub fn add(url: &str, destination: &str, revision: Option<&str>) -> bool {
+    let bare = bare_repo(url);
+
+    let output = if Path::new(&bare).is_dir() {
+        println!("Fetching {} into {}", url, bare);
+        // worktrees of builds that were interrupted are forgotten
+        git(&bare, &["worktree", "prune"]);
+        Command::new("git")
+            .arg("fetch")
+            .arg("--prune")
+            .arg("--force")
+            .arg(url)
+            .arg("refs/heads/*:refs/heads/*")
+            .arg("refs/tags/*:refs/tags/*")
+            .current_dir(&bare)
+            .output()
+            .expect("Failed to execute command")
+    } else {
+        println!("Cloning {} into {}", url, bare);
+        fs::create_dir_all(&bare).expect("Unable to create git cache directory");
+        Command::new("git")
+            .arg("clone")
+            .arg("--bare")
+            .arg(url)
+            .arg(&bare)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    if !output.status.success() {
+        eprintln!(
+            "Updating {} failed: {}",
+            bare,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        // a failed clone would otherwise be fetched into by the next build
+        if !Path::new(&bare).join("HEAD").is_file() {
+            let _ = fs::remove_dir_all(&bare);
+        }
+        return false;
+    }
+
+    println!("Adding worktree {} of {}", destination, bare);
+    git(
+        &bare,
+        &[
+            "worktree",
+            "add",
+            "--force",
+            "--detach",
+            destination,
+            revision.unwrap_or("HEAD"),
+        ],
+    )
+}
+
+fn git(bare: &str, args: &[&str]) -> bool {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(bare)
+        .output()
+        .expect("Failed to execute command");
+
+    if !output.status.success() {
+        eprintln!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    output.status.success()
+}
+
+// Removes the worktrees of a build when dropped,
+// so they are also removed when the build panics
+pub struct Worktrees {
+    // (bare repository, worktree)
+    worktrees: Vec<(String, String)>,
+}
+
+impl Worktrees {
+    pub fn new(pkgfile: &PkgFile, build_dir: &str) -> Worktrees {
+        let worktrees = pkgfile
+            .source
+            .iter()
+            .flatten()
+            .filter(|source| source.git_worktree.unwrap_or(false))
+            .map(|source| (bare_repo(&source.source), source.destination_dir(build_dir)))
+            .collect();
+
+        Worktrees { worktrees }
+    }
+}
+
+impl Drop for Worktrees {
+    fn drop(&mut self) {
+        for (bare, worktree) in &self.worktrees {
+            if !Path::new(bare).is_dir() {
+                continue;
+            }
+            if Path::new(worktree).exists() {
+                println!("Removing worktree {}", worktree);
+                git(bare, &["worktree", "remove", "--force", worktree]);
+            }
+            git(bare, &["worktree", "prune"]);
+        }
+    }
+}